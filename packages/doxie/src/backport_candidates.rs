@@ -0,0 +1,246 @@
+//! Build the queue of PRs that should be backported before the next patch release
+//!
+//! Anything merged to main since the stable line forked that hasn't been cherry-picked onto it yet is a
+//! candidate, as long as it isn't breaking. Candidates are ranked by their labels and whether
+//! `cargo semver-checks` considers them compatible, so the release manager can work down the list.
+//!
+//! Labels only catch the breaking changes someone remembered to label, so the semver checks are what
+//! actually keeps breaking changes out. Without them a candidate's `semver_compatible` stays `None`.
+
+use std::path::Path;
+
+use doxie_types::*;
+use git2::{Oid, Repository};
+
+use crate::{
+    backports::BackportIndex,
+    collect_pr_between,
+    commit_cache::CommitCache,
+    error::{Diagnostics, Result},
+    github::GitHub,
+    releases::TagIndex,
+    semver_checks, write_blob,
+};
+
+pub async fn collect_backport_candidates(
+    github: &dyn GitHub,
+    repo: &Repository,
+    repo_path: &Path,
+    config: &DoxieConfig,
+    out_dir: &Path,
+    run_semver_checks: bool,
+    mut diagnostics: Diagnostics,
+) -> Result<()> {
+    github.start_collector("backports");
+    let mut cache = CommitCache::load(out_dir, config);
+    let tag_index = TagIndex::new(repo, &config.releases, &mut cache)?;
+    let backports = BackportIndex::new(repo, config, tag_index.lines())?;
+
+    // The newest actual release - prereleases don't get patch releases
+    let Some(stable) = tag_index.newest_stable() else {
+        println!("No stable release found, nothing to backport to");
+        return Ok(());
+    };
+
+    // Walk main back to where the stable line forked off
+    let head = repo.head()?.peel_to_commit()?.id();
+    let base = repo.merge_base(stable.id, head)?;
+    let commits = collect_pr_between(
+        repo,
+        config,
+        &backports,
+        &mut cache,
+        &mut diagnostics,
+        head,
+        base,
+    )?;
+    cache.save()?;
+
+    let (owner, name) = config.main_owner_and_repo()?;
+    let mut candidates = vec![];
+
+    for commit in commits {
+        // Direct commits to main can't be cherry-picked by PR
+        let Some(id) = commit.id else {
+            continue;
+        };
+
+        // Anything that already made it to the stable line doesn't need to be backported
+        if !commit.backported_to.is_empty() || is_on_stable(repo, &commit, stable.id) {
+            continue;
+        }
+
+        // Without the labels we can't tell if it's breaking, so leave it out instead of guessing
+        let labels = github.labels(owner, name, id as u64).await;
+        let Some(labels) = diagnostics.recover(format!("labels of #{id}"), labels)? else {
+            continue;
+        };
+
+        let is_breaking = labels.iter().any(|label| {
+            config
+                .backports
+                .breaking_labels
+                .iter()
+                .any(|breaking| breaking.eq_ignore_ascii_case(label))
+        });
+
+        if is_breaking {
+            continue;
+        }
+
+        // Check the PR in isolation by comparing against its parent
+        let semver_compatible = match run_semver_checks {
+            true => {
+                let crates: Vec<String> = commit.changed_packages.iter().cloned().collect();
+                let baseline = format!("{}^", commit.commit_hash);
+                semver_checks::is_compatible(repo_path, &commit.commit_hash, &baseline, &crates)
+                    .await
+            }
+            false => None,
+        };
+
+        if semver_compatible == Some(false) {
+            continue;
+        }
+
+        candidates.push(BackportCandidate {
+            score: score(config, &labels, semver_compatible),
+            commit,
+            labels,
+            semver_compatible,
+        });
+    }
+
+    // Highest score first, and then oldest first so the cherry-picks apply in order
+    candidates.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(b.commit.head_index.cmp(&a.commit.head_index))
+    });
+
+    let candidates = BackportCandidates {
+        stable_tag: stable.name.clone(),
+        candidates,
+        diagnostics: diagnostics.into_entries(),
+    };
+
+    write_blob(out_dir, "backport_candidates.json", &candidates)
+}
+
+/// Commits from before the fork point are already part of the stable release
+fn is_on_stable(repo: &Repository, commit: &PrCommit, stable: Oid) -> bool {
+    let Ok(id) = Oid::from_str(&commit.commit_hash) else {
+        return false;
+    };
+
+    id == stable || repo.graph_descendant_of(stable, id).unwrap_or(false)
+}
+
+fn score(config: &DoxieConfig, labels: &[String], semver_compatible: Option<bool>) -> i64 {
+    let from_labels: i64 = labels
+        .iter()
+        .filter_map(|label| {
+            config
+                .backports
+                .label_scores
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(label))
+                .map(|(_, score)| *score)
+        })
+        .sum();
+
+    let from_semver = match semver_compatible {
+        Some(true) => 5,
+        _ => 0,
+    };
+
+    from_labels + from_semver
+}
+
+#[test]
+fn scores_labels_and_semver() {
+    let config = DoxieConfig::default();
+
+    let labels = vec!["Bug".to_string(), "enhancement".to_string()];
+    assert_eq!(score(&config, &labels, None), 10);
+    assert_eq!(score(&config, &labels, Some(true)), 15);
+    assert_eq!(score(&config, &[], Some(false)), 0);
+}
+
+#[cfg(test)]
+async fn collect_fixture(
+    github: &crate::github::FixtureGitHub,
+    keep_going: bool,
+) -> BackportCandidates {
+    let fixture = crate::fixtures::dioxus_like();
+    let out_dir = tempfile::tempdir().unwrap();
+
+    collect_backport_candidates(
+        github,
+        &fixture.repo,
+        fixture.repo.workdir().unwrap(),
+        &DoxieConfig::default(),
+        out_dir.path(),
+        false,
+        Diagnostics::new(keep_going),
+    )
+    .await
+    .unwrap();
+
+    serde_json::from_value(crate::fixtures::read_blob(
+        out_dir.path().join("backport_candidates.json"),
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn ranks_prs_missing_from_the_stable_line() {
+    // The saved open PRs don't know about the fixture's PRs, so give it #104 and #105 without labels
+    let mut open_prs = crate::fixtures::open_prs();
+    let dioxus = open_prs.prs.get_mut("dioxus").unwrap();
+    let template = dioxus.prs[0].clone();
+    for number in [104, 105] {
+        let mut pr = template.clone();
+        pr.number = number;
+        pr.labels = None;
+        dioxus.prs.push(pr);
+    }
+
+    let candidates = collect_fixture(&crate::github::FixtureGitHub::new(open_prs), false).await;
+
+    assert_eq!(candidates.stable_tag, "v0.5.1");
+
+    // #103 was cherry-picked onto v0.5 already and the release commit isn't a PR
+    let ids: Vec<_> = candidates
+        .candidates
+        .iter()
+        .map(|candidate| candidate.commit.id)
+        .collect();
+    assert_eq!(ids, [Some(104), Some(105)]);
+
+    assert!(candidates
+        .candidates
+        .iter()
+        .all(|candidate| candidate.semver_compatible.is_none()));
+
+    // Nothing to check means nothing was shown to be compatible
+    let fixture = crate::fixtures::dioxus_like();
+    assert_eq!(
+        semver_checks::is_compatible(fixture.repo.workdir().unwrap(), "HEAD", "HEAD^", &[]).await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn skips_candidates_whose_labels_fail_to_load() {
+    // Neither #104 nor #105 is in the saved open PRs
+    let candidates = collect_fixture(&crate::fixtures::github(), true).await;
+
+    assert!(candidates.candidates.is_empty());
+    let scopes: Vec<_> = candidates
+        .diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.scope.as_str())
+        .collect();
+    assert_eq!(scopes, ["labels of #105", "labels of #104"]);
+}
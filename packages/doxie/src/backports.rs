@@ -0,0 +1,215 @@
+//! Figure out which commits on main have been backported onto a stable line
+//!
+//! Once the next minor version is in the works on main, fixes get cherry-picked onto the `v0.x`
+//! branches and released from there. Those cherry-picks get a new sha, so we match them back up to the
+//! commits on main using the PR number, the `(cherry picked from commit ...)` trailer, and the patch-id
+//! of the diff.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::OnceLock,
+};
+
+use doxie_types::{DoxieConfig, ReleaseConfig};
+use git2::{Commit, Oid, Repository};
+use regex::Regex;
+
+use crate::{
+    error::Result,
+    pr_refs,
+    releases::{ReleaseLine, ReleaseTag},
+};
+
+/// Every commit that landed on a stable line, indexed by the different ways we can match them to main
+#[derive(Debug, Default)]
+pub struct BackportIndex {
+    by_pr: HashMap<usize, BTreeSet<String>>,
+    by_cherry_pick: HashMap<String, BTreeSet<String>>,
+    by_patch_id: HashMap<Oid, BTreeSet<String>>,
+}
+
+impl BackportIndex {
+    /// Walk every stable tag and branch of the release lines and index the commits that aren't on HEAD
+    ///
+    /// A commit is labeled with the first release tag that contains it, or the stable branch if it
+    /// hasn't been released yet.
+    pub fn new(
+        repo: &Repository,
+        config: &DoxieConfig,
+        lines: &BTreeMap<ReleaseLine, Vec<ReleaseTag>>,
+    ) -> Result<Self> {
+        let mut index = Self::default();
+
+        let Some(head) = repo.head().ok().and_then(|head| head.target()) else {
+            return Ok(index);
+        };
+
+        for (&(major, minor), tags) in lines.iter() {
+            // Tags that are reachable from HEAD were cut from main, so there's nothing backported in them
+            let mut refs: Vec<(String, Oid)> = tags
+                .iter()
+                .filter(|tag| !is_ancestor(repo, tag.id, head))
+                .map(|tag| (tag.name.clone(), tag.id))
+                .collect();
+
+            if let Some(branch) = stable_branch(repo, &config.releases, major, minor) {
+                refs.push(branch);
+            }
+
+            // Tags are sorted, so the first ref to reach a commit is the release that shipped it
+            let mut seen = HashSet::new();
+
+            for (name, id) in refs {
+                if is_ancestor(repo, id, head) {
+                    continue;
+                }
+
+                let mut revwalk = repo.revwalk()?;
+                revwalk.push(id)?;
+                revwalk.hide(head)?;
+
+                for commit_id in revwalk.flatten() {
+                    if !seen.insert(commit_id) {
+                        continue;
+                    }
+
+                    if let Ok(commit) = repo.find_commit(commit_id) {
+                        index.insert(repo, config, &commit, &name);
+                    }
+                }
+            }
+        }
+
+        Ok(index)
+    }
+
+    fn insert(&mut self, repo: &Repository, config: &DoxieConfig, commit: &Commit, label: &str) {
+        let message = String::from_utf8_lossy(commit.message_bytes());
+
+        if let Some(id) = pr_refs::parse(&message).merged_pr(&config.main_repo) {
+            self.by_pr.entry(id).or_default().insert(label.to_string());
+        }
+
+        for sha in cherry_picked_from(&message) {
+            self.by_cherry_pick
+                .entry(sha)
+                .or_default()
+                .insert(label.to_string());
+        }
+
+        if let Some(patch_id) = patch_id(repo, commit) {
+            self.by_patch_id
+                .entry(patch_id)
+                .or_default()
+                .insert(label.to_string());
+        }
+    }
+
+    /// Get the stable tags and branches a commit on main has been backported to
+    ///
+    /// Sorted and deduplicated so it's stable across runs
+    pub fn backported_to(
+        &self,
+        commit_id: Oid,
+        pr: Option<usize>,
+        patch_id: Option<Oid>,
+    ) -> Vec<String> {
+        let mut out = BTreeSet::new();
+
+        if let Some(labels) = pr.and_then(|pr| self.by_pr.get(&pr)) {
+            out.extend(labels.iter().cloned());
+        }
+
+        // Trailers can use abbreviated shas, so match on the prefix
+        let sha = commit_id.to_string();
+        for (picked, labels) in self.by_cherry_pick.iter() {
+            if sha.starts_with(picked.as_str()) {
+                out.extend(labels.iter().cloned());
+            }
+        }
+
+        if let Some(labels) = patch_id.and_then(|id| self.by_patch_id.get(&id)) {
+            out.extend(labels.iter().cloned());
+        }
+
+        out.into_iter().collect()
+    }
+}
+
+/// Compute the patch-id of a commit against its first parent
+///
+/// Two commits with the same changes have the same patch-id, even if they were applied on different bases
+pub fn patch_id(repo: &Repository, commit: &Commit) -> Option<Oid> {
+    let parent = commit.parent(0).ok()?;
+
+    let diff = repo
+        .diff_tree_to_tree(Some(&parent.tree().ok()?), Some(&commit.tree().ok()?), None)
+        .ok()?;
+
+    diff.patchid(None).ok()
+}
+
+/// Pull the shas out of any `(cherry picked from commit ...)` trailers that `git cherry-pick -x` adds
+fn cherry_picked_from(message: &str) -> Vec<String> {
+    static TRAILER: OnceLock<Regex> = OnceLock::new();
+
+    let trailer = TRAILER
+        .get_or_init(|| Regex::new(r"\(cherry picked from commit ([0-9a-f]{7,40})\)").unwrap());
+
+    trailer
+        .captures_iter(message)
+        .map(|captures| captures[1].to_string())
+        .collect()
+}
+
+/// Find the stable branch for a release line, preferring a local branch over a remote one
+fn stable_branch(
+    repo: &Repository,
+    config: &ReleaseConfig,
+    major: u64,
+    minor: u64,
+) -> Option<(String, Oid)> {
+    let name = config.branch_for(major, minor);
+
+    let mut remote = None;
+
+    for (branch, kind) in repo.branches(None).ok()?.flatten() {
+        let Ok(Some(branch_name)) = branch.name() else {
+            continue;
+        };
+
+        let Some(target) = branch.get().target() else {
+            continue;
+        };
+
+        match kind {
+            git2::BranchType::Local if branch_name == name => return Some((name, target)),
+
+            // Remote branches are in the form of `origin/v0.5`
+            git2::BranchType::Remote
+                if branch_name.split_once('/').map(|(_, b)| b) == Some(name.as_str()) =>
+            {
+                remote = Some((name.clone(), target));
+            }
+
+            _ => {}
+        }
+    }
+
+    remote
+}
+
+fn is_ancestor(repo: &Repository, commit: Oid, head: Oid) -> bool {
+    commit == head || repo.graph_descendant_of(head, commit).unwrap_or(false)
+}
+
+#[test]
+fn parses_cherry_pick_trailers() {
+    let message = "Fix the thing (#2342)\n\n(cherry picked from commit 1a2142d9c2b505d3cf2a4c6b50f292465e227841)";
+    assert_eq!(
+        cherry_picked_from(message),
+        vec!["1a2142d9c2b505d3cf2a4c6b50f292465e227841"]
+    );
+
+    assert!(cherry_picked_from("Fix the thing (#2342)").is_empty());
+}
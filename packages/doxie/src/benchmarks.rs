@@ -0,0 +1,274 @@
+//! Run the criterion benchmarks and read back what they measured
+//!
+//! criterion leaves an `estimates.json` under `target/criterion/<benchmark>/new` for every benchmark it
+//! runs, with the mean, median and standard deviation it bootstrapped and a confidence interval for each.
+//! We run the configured commands and then pick up every estimate written while they ran, so results left
+//! behind by older runs or deleted benchmarks don't sneak into the stats.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use doxie_types::*;
+use serde::Deserialize;
+use tokio::process::Command;
+
+use crate::error::{Diagnostics, DoxieError, Result};
+
+/// Run the benchmark commands in a checkout and collect the results, keyed by benchmark
+pub async fn run(
+    repo_path: &Path,
+    config: &BenchConfig,
+    diagnostics: &mut Diagnostics,
+) -> Result<HashMap<String, PerfStats>> {
+    let started = SystemTime::now();
+
+    for command in &config.commands {
+        let result = run_command(repo_path, command).await;
+        diagnostics.recover(format!("benchmark command `{command}`"), result)?;
+    }
+
+    read_estimates(
+        &repo_path.join(&config.criterion_dir),
+        Some(started),
+        diagnostics,
+    )
+}
+
+/// Run one of the configured commands, with its output going straight to the log
+pub async fn run_command(dir: &Path, command: &str) -> Result<()> {
+    let failed = |reason: String| DoxieError::Command {
+        command: command.to_string(),
+        reason,
+    };
+
+    let mut args = command.split_whitespace();
+    let program = args
+        .next()
+        .ok_or_else(|| failed("it's empty".to_string()))?;
+
+    let status = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .await
+        .map_err(|err| failed(err.to_string()))?;
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(failed(status.to_string())),
+    }
+}
+
+/// Read every benchmark criterion wrote to `criterion_dir`, skipping the ones written before `since`
+///
+/// A missing dir just means nothing ran, so there's nothing to read
+pub fn read_estimates(
+    criterion_dir: &Path,
+    since: Option<SystemTime>,
+    diagnostics: &mut Diagnostics,
+) -> Result<HashMap<String, PerfStats>> {
+    let mut found = vec![];
+    find_estimates(criterion_dir, &mut found);
+
+    let mut perf = HashMap::new();
+
+    for estimates in found {
+        let written = std::fs::metadata(&estimates).and_then(|meta| meta.modified());
+        if let (Some(since), Ok(written)) = (since, written) {
+            if written < since {
+                continue;
+            }
+        }
+
+        // `<benchmark>/new/estimates.json`
+        let dir = estimates.parent().and_then(Path::parent).unwrap();
+        let name = benchmark_name(criterion_dir, dir);
+
+        let stats = diagnostics.recover(format!("benchmark {name}"), read_stats(&name, dir))?;
+        if let Some(stats) = stats {
+            perf.insert(name, stats);
+        }
+    }
+
+    Ok(perf)
+}
+
+/// Collect the `new/estimates.json` of every benchmark under `dir`
+fn find_estimates(dir: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+
+        match entry.file_name().to_str() {
+            // The html report and the saved baselines of a benchmark
+            Some("report" | "base" | "change") => {}
+            Some("new") => {
+                let estimates = path.join("estimates.json");
+                if estimates.is_file() {
+                    found.push(estimates);
+                }
+            }
+            _ => find_estimates(&path, found),
+        }
+    }
+}
+
+/// The id criterion gave the benchmark, ie `diff/keyed/1000`
+///
+/// criterion mangles the id into a dir name, so the real one comes from the `benchmark.json` next to the
+/// estimates, falling back to the path of the dir
+fn benchmark_name(criterion_dir: &Path, dir: &Path) -> String {
+    #[derive(Deserialize)]
+    struct BenchmarkId {
+        full_id: String,
+    }
+
+    let id = std::fs::read_to_string(dir.join("new").join("benchmark.json"))
+        .ok()
+        .and_then(|raw| serde_json::from_str::<BenchmarkId>(&raw).ok());
+
+    match id {
+        Some(id) => id.full_id,
+        None => dir
+            .strip_prefix(criterion_dir)
+            .unwrap_or(dir)
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    }
+}
+
+fn read_stats(name: &str, dir: &Path) -> Result<PerfStats> {
+    let path = dir.join("new").join("estimates.json");
+    let raw = std::fs::read_to_string(&path).map_err(DoxieError::io("read", &path))?;
+    let estimates: Estimates = serde_json::from_str(&raw)?;
+
+    Ok(PerfStats {
+        name: name.to_string(),
+        raw_walltime: estimates.mean.point_estimate,
+        normalized_walltime: None,
+        mean: estimates.mean.into(),
+        median: estimates.median.into(),
+        std_dev: estimates.std_dev.into(),
+    })
+}
+
+/// The parts of criterion's `estimates.json` we keep
+#[derive(Deserialize)]
+struct Estimates {
+    mean: CriterionEstimate,
+    median: CriterionEstimate,
+    std_dev: CriterionEstimate,
+}
+
+#[derive(Deserialize)]
+struct CriterionEstimate {
+    confidence_interval: ConfidenceInterval,
+    point_estimate: f64,
+}
+
+#[derive(Deserialize)]
+struct ConfidenceInterval {
+    confidence_level: f64,
+    lower_bound: f64,
+    upper_bound: f64,
+}
+
+impl From<CriterionEstimate> for Estimate {
+    fn from(estimate: CriterionEstimate) -> Self {
+        Self {
+            point: estimate.point_estimate,
+            lower_bound: estimate.confidence_interval.lower_bound,
+            upper_bound: estimate.confidence_interval.upper_bound,
+            confidence_level: estimate.confidence_interval.confidence_level,
+        }
+    }
+}
+
+#[test]
+fn reads_criterion_estimates() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let write_benchmark = |dir: &Path, mean: f64, full_id: Option<&str>| {
+        let new = dir.join("new");
+        std::fs::create_dir_all(&new).unwrap();
+
+        let estimate = |point: f64| {
+            serde_json::json!({
+                "confidence_interval": {
+                    "confidence_level": 0.95,
+                    "lower_bound": point - 2.0,
+                    "upper_bound": point + 2.0,
+                },
+                "point_estimate": point,
+                "standard_error": 1.0,
+            })
+        };
+        let estimates = serde_json::json!({
+            "mean": estimate(mean),
+            "median": estimate(mean - 10.0),
+            "median_abs_dev": estimate(5.0),
+            "slope": null,
+            "std_dev": estimate(20.0),
+        });
+        std::fs::write(new.join("estimates.json"), estimates.to_string()).unwrap();
+
+        if let Some(full_id) = full_id {
+            let id = serde_json::json!({ "full_id": full_id, "directory_name": "ignored" });
+            std::fs::write(new.join("benchmark.json"), id.to_string()).unwrap();
+        }
+    };
+
+    write_benchmark(&dir.path().join("create_rows"), 1000.0, None);
+    write_benchmark(
+        &dir.path().join("diff").join("keyed_1000"),
+        500.0,
+        Some("diff/keyed/1000"),
+    );
+
+    // Left over from a run before this one
+    let stale = dir.path().join("removed_bench");
+    write_benchmark(&stale, 1.0, None);
+    let an_hour_ago = SystemTime::now() - std::time::Duration::from_secs(3600);
+    std::fs::File::options()
+        .write(true)
+        .open(stale.join("new").join("estimates.json"))
+        .unwrap()
+        .set_modified(an_hour_ago)
+        .unwrap();
+
+    // The report isn't a benchmark, and neither is a broken one if we're keeping going
+    std::fs::create_dir_all(dir.path().join("report")).unwrap();
+    let broken = dir.path().join("broken").join("new");
+    std::fs::create_dir_all(&broken).unwrap();
+    std::fs::write(broken.join("estimates.json"), "{}").unwrap();
+
+    let since = an_hour_ago + std::time::Duration::from_secs(60);
+    let mut diagnostics = Diagnostics::new(true);
+    let perf = read_estimates(dir.path(), Some(since), &mut diagnostics).unwrap();
+
+    let mut names: Vec<_> = perf.keys().cloned().collect();
+    names.sort();
+    assert_eq!(names, ["create_rows", "diff/keyed/1000"]);
+
+    let keyed = &perf["diff/keyed/1000"];
+    assert_eq!(keyed.raw_walltime, 500.0);
+    assert_eq!(keyed.normalized_walltime, None);
+    assert_eq!(keyed.median.point, 490.0);
+    assert_eq!(keyed.mean.confidence_level, 0.95);
+    assert_eq!(keyed.std_dev.upper_bound, 22.0);
+
+    let diagnostics = diagnostics.into_entries();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].scope, "benchmark broken");
+}
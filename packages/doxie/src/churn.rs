@@ -0,0 +1,81 @@
+//! Count the lines each commit changes
+//!
+//! Lockfiles, snapshots and generated code can dwarf the real changes of a PR, so anything matching the
+//! `exclude` globs of the churn config doesn't count.
+
+use std::path::Path;
+
+use doxie_types::*;
+use git2::{Diff, Patch};
+
+/// The compiled `exclude` globs of the churn config
+pub struct ChurnFilter {
+    exclude: Vec<glob::Pattern>,
+}
+
+impl ChurnFilter {
+    /// Compile the globs, skipping any that are malformed
+    pub fn new(config: &ChurnConfig) -> Self {
+        let exclude = config
+            .exclude
+            .iter()
+            .filter_map(|pattern| match glob::Pattern::new(pattern) {
+                Ok(pattern) => Some(pattern),
+                Err(err) => {
+                    eprintln!("Skipping invalid churn exclude {pattern:?}: {err}");
+                    None
+                }
+            })
+            .collect();
+
+        Self { exclude }
+    }
+
+    pub fn is_excluded(&self, file: &Path) -> bool {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+
+        self.exclude
+            .iter()
+            .any(|pattern| pattern.matches_path_with(file, options))
+    }
+}
+
+/// The lines changed in a single file of a diff
+///
+/// Binary files count as changed but don't have any lines
+pub fn file_churn(diff: &Diff, idx: usize) -> ChurnStats {
+    let (insertions, deletions) = Patch::from_diff(diff, idx)
+        .ok()
+        .flatten()
+        .and_then(|patch| patch.line_stats().ok())
+        .map(|(_, insertions, deletions)| (insertions, deletions))
+        .unwrap_or_default();
+
+    ChurnStats {
+        insertions,
+        deletions,
+        files_changed: 1,
+    }
+}
+
+#[test]
+fn excludes_generated_files() {
+    let filter = ChurnFilter::new(&ChurnConfig {
+        exclude: vec![
+            "Cargo.lock".to_string(),
+            "**/*.snap".to_string(),
+            "packages/interpreter/src/js/**".to_string(),
+            "[invalid".to_string(),
+        ],
+    });
+
+    assert!(filter.is_excluded(Path::new("Cargo.lock")));
+    assert!(filter.is_excluded(Path::new("packages/autofmt/tests/snapshots/simple.snap")));
+    assert!(filter.is_excluded(Path::new("packages/interpreter/src/js/core.js")));
+
+    assert!(!filter.is_excluded(Path::new("packages/cli/Cargo.lock.rs")));
+    assert!(!filter.is_excluded(Path::new("packages/core/src/lib.rs")));
+}
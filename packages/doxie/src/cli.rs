@@ -0,0 +1,264 @@
+//! The command line interface for doxie
+//!
+//! Every piece of the bot is exposed as its own subcommand so we can run exactly the part we need,
+//! either locally or from a workflow. All the arguments can also be passed in as environment variables,
+//! which is the easiest way to configure things from a github action.
+
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use doxie_types::DoxieConfig;
+
+use crate::{
+    error::{Diagnostics, DoxieError, Result},
+    github::{Client, DryRun, FixtureGitHub, GitHub},
+    OUTPUT_DIR,
+};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "doxie",
+    version,
+    about = "A helpful robot that automates the dioxus org"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Walk the release tags of the repo and save the changed crates of every commit to `commits.json`
+    Commits {
+        #[command(flatten)]
+        shared: SharedArgs,
+
+        /// Run `cargo semver-checks` on the unreleased commits of every line against its last release
+        #[arg(long)]
+        semver_checks: bool,
+    },
+
+    /// List the open PRs across the org and save them to `open_prs.json`
+    OpenPrs {
+        #[command(flatten)]
+        shared: SharedArgs,
+
+        /// Run `cargo semver-checks` on the open PRs of the main repo
+        #[arg(long)]
+        semver_checks: bool,
+    },
+
+    /// Run the benchmarks, size checks, etc and save their output
+    Stats {
+        #[command(flatten)]
+        shared: SharedArgs,
+
+        /// Also run the benchmarks of this revision in alternating rounds and save how the checked out
+        /// commit compares to it in `perf_comparison.json`
+        #[arg(long, env = "DOXIE_PERF_BASELINE")]
+        baseline: Option<String>,
+
+        /// The branch whose history in `stats_history.json` the stats get added to. Defaults to the
+        /// checked out branch, and nothing gets added on a detached HEAD
+        #[arg(long, env = "DOXIE_STATS_BRANCH")]
+        branch: Option<String>,
+    },
+
+    /// Look for commits where a benchmark got lastingly slower or faster in `stats_history.json` and save
+    /// them to `regressions.json`
+    Regressions(SharedArgs),
+
+    /// Write (or overwrite) the status comment on a PR, running the semver checks for it
+    Comment {
+        #[command(flatten)]
+        shared: SharedArgs,
+
+        /// The number of the PR to comment on
+        #[arg(long, env = "DOXIE_PR")]
+        pr: u64,
+    },
+
+    /// Write the status blob that the status page reads
+    StatusBlob(SharedArgs),
+
+    /// List the PRs merged to main since the last stable release that still need to be backported
+    Backports {
+        #[command(flatten)]
+        shared: SharedArgs,
+
+        /// Run `cargo semver-checks` on every candidate. This checks out each commit, so it's slow
+        #[arg(long)]
+        semver_checks: bool,
+    },
+
+    /// Compare every crate in the workspace against crates.io and save the ones with unreleased changes
+    /// to `release_queue.json`
+    ReleaseQueue {
+        #[command(flatten)]
+        shared: SharedArgs,
+
+        /// The path to a local crates.io index, either a git checkout of `crates.io-index` or cargo's
+        /// sparse cache under `~/.cargo/registry/index/<registry>/.cache`
+        #[arg(long, env = "DOXIE_CRATES_INDEX")]
+        index_path: PathBuf,
+    },
+
+    /// Collect the commits and the open PRs in one go - this is what runs when a PR is merged
+    Artifact(SharedArgs),
+}
+
+impl Command {
+    pub fn shared(&self) -> &SharedArgs {
+        match self {
+            Command::Commits { shared, .. }
+            | Command::OpenPrs { shared, .. }
+            | Command::Stats { shared, .. }
+            | Command::Comment { shared, .. }
+            | Command::Backports { shared, .. }
+            | Command::ReleaseQueue { shared, .. } => shared,
+            Command::Regressions(shared)
+            | Command::StatusBlob(shared)
+            | Command::Artifact(shared) => shared,
+        }
+    }
+}
+
+/// The arguments every subcommand takes
+#[derive(Args, Debug, Clone)]
+pub struct SharedArgs {
+    /// The path to a local checkout of the repo we're reporting on
+    #[arg(long, env = "DOXIE_REPO_PATH", default_value = ".")]
+    pub repo_path: PathBuf,
+
+    /// The directory the json blobs get written to
+    #[arg(long, env = "DOXIE_OUTPUT_DIR", default_value = OUTPUT_DIR)]
+    pub output_dir: PathBuf,
+
+    /// The github repo in the form of `owner/repo`, overriding the `main_repo` of the config
+    ///
+    /// Not read from `GITHUB_REPOSITORY`, which every workflow sets to the repo the workflow runs in -
+    /// the bot runs from its own repo but reports on dioxus
+    #[arg(long, env = "DOXIE_GITHUB_REPO")]
+    pub github_repo: Option<String>,
+
+    /// The path to the `doxie.toml` config. If it doesn't exist, the defaults for the dioxus org are used,
+    /// but any other error reading it stops the run
+    #[arg(long, env = "DOXIE_CONFIG", default_value = "doxie.toml")]
+    pub config: PathBuf,
+
+    /// A github token for the API. Without one we're limited to 60 requests an hour, so the timeline
+    /// lookups get skipped and we can't comment on PRs
+    #[arg(long, env = "GITHUB_TOKEN", hide_env_values = true)]
+    pub github_token: Option<String>,
+
+    /// Record the repos and commits that fail to collect in the output instead of stopping the run
+    #[arg(long, env = "DOXIE_KEEP_GOING")]
+    pub keep_going: bool,
+
+    /// Answer the github requests from a saved `open_prs.json` instead of the api, so the bot can run
+    /// without a token or network
+    #[arg(long, env = "DOXIE_GITHUB_FIXTURE")]
+    pub github_fixture: Option<PathBuf>,
+
+    /// Print the comments the bot would write instead of posting them
+    #[arg(long, env = "DOXIE_DRY_RUN")]
+    pub dry_run: bool,
+}
+
+impl SharedArgs {
+    /// Load the config and apply any overrides passed on the command line
+    pub fn load_config(&self) -> Result<DoxieConfig> {
+        let mut config = match std::fs::read_to_string(&self.config) {
+            Ok(raw) => DoxieConfig::from_toml(&raw).map_err(|source| DoxieError::Config {
+                path: self.config.clone(),
+                source: Box::new(source),
+            })?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => DoxieConfig::default(),
+            Err(err) => return Err(DoxieError::io("read", &self.config)(err)),
+        };
+
+        if let Some(repo) = &self.github_repo {
+            config.main_repo = repo.clone();
+        }
+
+        // Catch a bad repo here instead of halfway through a collector
+        config.main_owner_and_repo()?;
+
+        Ok(config)
+    }
+
+    pub fn diagnostics(&self) -> Diagnostics {
+        Diagnostics::new(self.keep_going)
+    }
+
+    /// Set up the github client, authenticating it if we have a token
+    pub fn github(&self, config: &DoxieConfig) -> Result<Box<dyn GitHub>> {
+        let github: Box<dyn GitHub> = match &self.github_fixture {
+            Some(path) => Box::new(FixtureGitHub::load(path)?),
+            None => {
+                let mut builder = octocrab::Octocrab::builder();
+                if let Some(token) = &self.github_token {
+                    builder = builder.personal_token(token.clone());
+                }
+
+                Box::new(Client::new(
+                    builder.build()?,
+                    &config.github,
+                    &self.output_dir,
+                ))
+            }
+        };
+
+        Ok(match self.dry_run {
+            true => Box::new(DryRun(github)),
+            false => github,
+        })
+    }
+}
+
+#[test]
+fn rejects_a_main_repo_without_an_owner() {
+    let load = |repo: &str| {
+        Cli::parse_from([
+            "doxie",
+            "status-blob",
+            "--config",
+            "missing-doxie.toml",
+            "--github-repo",
+            repo,
+        ])
+        .command
+        .shared()
+        .load_config()
+    };
+
+    assert!(matches!(load("dioxus"), Err(DoxieError::InvalidRepo(repo)) if repo == "dioxus"));
+    assert_eq!(
+        load("dioxuslabs/blitz").unwrap().main_repo,
+        "dioxuslabs/blitz"
+    );
+}
+
+#[test]
+fn only_falls_back_to_the_default_config_when_it_is_missing() {
+    let dir = tempfile::tempdir().unwrap();
+    let load = |config: &std::path::Path| -> Result<DoxieConfig> {
+        Cli::parse_from([
+            "doxie".as_ref(),
+            "status-blob".as_ref(),
+            "--config".as_ref(),
+            config.as_os_str(),
+        ])
+        .command
+        .shared()
+        .load_config()
+    };
+
+    assert_eq!(
+        load(&dir.path().join("doxie.toml")).unwrap().main_repo,
+        DoxieConfig::default().main_repo
+    );
+
+    // A config we can't read shouldn't be silently swapped for the defaults
+    assert!(matches!(load(dir.path()), Err(DoxieError::Io { .. })));
+}
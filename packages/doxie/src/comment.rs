@@ -0,0 +1,170 @@
+//! The status comment doxie leaves on every PR
+//!
+//! There's only ever one comment per PR. It starts with a hidden marker so we can find it again and
+//! overwrite it whenever the PR changes, instead of piling up new comments.
+
+use doxie_types::*;
+
+use crate::{error::Result, github::GitHub};
+
+/// The hidden marker at the top of the comment
+pub const MARKER: &str = "<!-- doxie-status -->";
+
+/// Render the body of the status comment
+///
+/// `semver_findings` is `None` if the checks couldn't run, which is different from running and finding
+/// nothing. `size_diff` is the table from [`sizes::render_diff`](crate::sizes::render_diff), and the
+/// section is left out if we don't have the sizes of both the base and the head. The body only depends
+/// on its inputs so re-running on the same commit doesn't change it.
+pub fn render(
+    head_sha: &str,
+    semver_findings: Option<&[SemverFinding]>,
+    linked_issues: &[LinkedIssue],
+    size_diff: Option<&str>,
+) -> String {
+    let mut body = format!("{MARKER}\n## Doxie status\n\n");
+
+    body.push_str("### Linked issues\n\n");
+
+    match linked_issues.is_empty() {
+        true => body.push_str(
+            "This PR doesn't link an issue. If it fixes one, add `Closes #123` to the description so \
+             the issue gets closed when this is merged.\n",
+        ),
+        false => {
+            for issue in linked_issues {
+                let kind = match issue.closes {
+                    true => "Closes",
+                    false => "References",
+                };
+
+                body.push_str(&format!("- {kind} {}#{}\n", issue.repo, issue.number));
+            }
+        }
+    }
+
+    body.push('\n');
+
+    body.push_str("### Semver\n\n");
+
+    match semver_findings {
+        None => body.push_str("`cargo semver-checks` couldn't run on this PR.\n"),
+        Some([]) => body.push_str("No semver issues found in the changed crates.\n"),
+        Some(findings) => {
+            let failures = findings
+                .iter()
+                .filter(|finding| finding.severity == SemverSeverity::Failure)
+                .count();
+
+            if failures > 0 {
+                body.push_str(&format!(
+                    "> [!WARNING]\n> This PR has {failures} breaking change(s). It can't be backported to a \
+                     stable release and needs to wait for the next minor version.\n\n"
+                ));
+            }
+
+            body.push_str("| Crate | Lint | Item |\n| --- | --- | --- |\n");
+
+            for finding in findings {
+                let severity = match finding.severity {
+                    SemverSeverity::Failure => "failure",
+                    SemverSeverity::Warning => "warning",
+                };
+
+                body.push_str(&format!(
+                    "| `{}` | {severity}: `{}` - {} | {} |\n",
+                    finding.krate,
+                    finding.lint,
+                    finding.description,
+                    finding
+                        .item
+                        .as_deref()
+                        .map(|item| format!("`{item}`"))
+                        .unwrap_or_default(),
+                ));
+            }
+        }
+    }
+
+    if let Some(size_diff) = size_diff {
+        body.push_str("\n### Wasm size\n\n");
+        body.push_str(size_diff);
+    }
+
+    body.push_str(&format!("\n<sub>Updated for {head_sha}</sub>\n"));
+
+    body
+}
+
+/// Create the status comment, or overwrite it if the PR already has one
+pub async fn upsert(
+    github: &dyn GitHub,
+    owner: &str,
+    repo: &str,
+    pr: u64,
+    body: &str,
+) -> Result<()> {
+    let comments = github.comments(owner, repo, pr).await?;
+
+    let existing = comments
+        .into_iter()
+        .find(|comment| comment.body.starts_with(MARKER));
+
+    match existing {
+        Some(comment) => github.update_comment(owner, repo, comment.id, body).await,
+        None => github.create_comment(owner, repo, pr, body).await,
+    }
+}
+
+#[test]
+fn renders_status_comment() {
+    let finding = |severity| SemverFinding {
+        krate: "dioxus-core".to_string(),
+        lint: "function_missing".to_string(),
+        severity,
+        item: Some("function dioxus_core::prelude::use_hook".to_string()),
+        description: "pub fn removed or renamed".to_string(),
+    };
+
+    let body = render(
+        "abc123",
+        Some(&[finding(SemverSeverity::Failure)]),
+        &[],
+        None,
+    );
+    assert!(body.starts_with(MARKER));
+    assert!(body.contains("This PR doesn't link an issue"));
+    assert!(body.contains("This PR has 1 breaking change(s)"));
+    assert!(body.contains(
+        "| `dioxus-core` | failure: `function_missing` - pub fn removed or renamed | `function dioxus_core::prelude::use_hook` |"
+    ));
+
+    let body = render(
+        "abc123",
+        Some(&[finding(SemverSeverity::Warning)]),
+        &[],
+        None,
+    );
+    assert!(!body.contains("[!WARNING]"));
+    assert!(!body.contains("### Wasm size"));
+
+    let body = render(
+        "abc123",
+        Some(&[]),
+        &[],
+        Some("| Build | Base | Head | Change |\n"),
+    );
+    assert!(body.contains("### Wasm size\n\n| Build |"));
+
+    assert!(render("abc123", Some(&[]), &[], None).contains("No semver issues found"));
+    assert!(render("abc123", None, &[], None).contains("couldn't run"));
+
+    let issue = LinkedIssue {
+        repo: "DioxusLabs/dioxus".to_string(),
+        number: 2001,
+        closes: true,
+    };
+    let body = render("abc123", Some(&[]), &[issue], None);
+    assert!(body.contains("- Closes DioxusLabs/dioxus#2001"));
+    assert!(!body.contains("doesn't link an issue"));
+}
@@ -0,0 +1,199 @@
+//! Remember what we learned about each commit between runs
+//!
+//! Diffing every commit since the oldest reported release is by far the slowest part of a run, but a
+//! commit never changes once it's made. We keep the results of each diff in a json file in the output
+//! dir, keyed by sha, so later runs only have to diff the commits that landed since.
+//!
+//! Anything that depends on the rest of the history - backports, the dependency graph of a release,
+//! where the commit sits in a release - is still worked out fresh on every run.
+//!
+//! The issues linked from the timeline of released PRs are kept here too. They aren't set in stone like
+//! a diff, but by the time a PR ships nobody is linking issues to it anymore, and it saves a request per
+//! PR on every run.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use doxie_types::*;
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{DoxieError, Result};
+
+/// Bump this whenever the meaning of a cached field changes so old caches get thrown away
+const CACHE_VERSION: u32 = 1;
+
+const CACHE_FILE: &str = "commit_cache.json";
+
+/// The parts of a commit that only depend on its diff
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CachedCommit {
+    pub changed_packages: HashSet<String>,
+    pub affects_workspace: bool,
+    pub patch_id: Option<String>,
+    pub churn: ChurnStats,
+    pub churn_by_crate: HashMap<String, ChurnStats>,
+}
+
+impl CachedCommit {
+    pub fn patch_id(&self) -> Option<Oid> {
+        self.patch_id
+            .as_deref()
+            .and_then(|id| Oid::from_str(id).ok())
+    }
+}
+
+/// A tag we've peeled before
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedTag {
+    /// What the tag ref points at - an annotated tag object or the commit itself
+    target: String,
+
+    /// The commit it peels to
+    commit: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CommitCache {
+    version: u32,
+
+    /// The config the cache was built with. Changing it changes the results of a diff
+    config: String,
+
+    commits: HashMap<String, CachedCommit>,
+
+    tags: HashMap<String, CachedTag>,
+
+    /// The issues linked from the timeline of a PR, keyed by `owner/repo#number`
+    #[serde(default)]
+    timelines: HashMap<String, Vec<LinkedIssue>>,
+
+    /// Where to save the cache, `None` if it only lives for this run
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl CommitCache {
+    /// Load the cache from the output dir, starting fresh if it's missing, stale or unreadable
+    pub fn load(out_dir: &Path, config: &DoxieConfig) -> Self {
+        let path = out_dir.join(CACHE_FILE);
+        let fingerprint = fingerprint(config);
+
+        let cache = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|raw| serde_json::from_str::<CommitCache>(&raw).ok())
+            .filter(|cache| cache.version == CACHE_VERSION && cache.config == fingerprint);
+
+        let mut cache = cache.unwrap_or_else(|| Self {
+            version: CACHE_VERSION,
+            config: fingerprint,
+            ..Default::default()
+        });
+
+        cache.path = Some(path);
+        cache
+    }
+
+    /// A cache that's never saved, for one-off runs and tests
+    pub fn in_memory() -> Self {
+        Self {
+            version: CACHE_VERSION,
+            ..Default::default()
+        }
+    }
+
+    pub fn commit(&self, id: Oid) -> Option<&CachedCommit> {
+        self.commits.get(&id.to_string())
+    }
+
+    pub fn insert_commit(&mut self, id: Oid, commit: CachedCommit) {
+        self.commits.insert(id.to_string(), commit);
+    }
+
+    pub fn timeline(&self, pr: &str) -> Option<&Vec<LinkedIssue>> {
+        self.timelines.get(pr)
+    }
+
+    pub fn insert_timeline(&mut self, pr: String, issues: Vec<LinkedIssue>) {
+        self.timelines.insert(pr, issues);
+    }
+
+    /// Peel a tag to its commit, skipping the peel if the tag still points where it did last time
+    pub fn peel_tag(&mut self, repo: &Repository, name: &str) -> Option<Oid> {
+        let reference = repo.find_reference(&format!("refs/tags/{name}")).ok()?;
+        let target = reference.target()?.to_string();
+
+        if let Some(cached) = self.tags.get(name).filter(|cached| cached.target == target) {
+            return Oid::from_str(&cached.commit).ok();
+        }
+
+        let commit = reference.peel_to_commit().ok()?.id();
+
+        self.tags.insert(
+            name.to_string(),
+            CachedTag {
+                target,
+                commit: commit.to_string(),
+            },
+        );
+
+        Some(commit)
+    }
+
+    /// Write the cache back to the output dir, if it came from there
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(DoxieError::io("create", dir))?;
+        }
+
+        // This gets big, so it's never pretty printed
+        let blob = serde_json::to_string(self)?;
+        std::fs::write(path, blob).map_err(DoxieError::io("write", path))
+    }
+}
+
+/// The parts of the config that change what a diff turns into
+fn fingerprint(config: &DoxieConfig) -> String {
+    serde_json::to_string(&(&config.workspace, &config.churn)).unwrap()
+}
+
+#[test]
+fn reuses_the_cache_until_the_config_changes() {
+    let dir = tempfile::tempdir().unwrap();
+    let dir = dir.path();
+
+    let config = DoxieConfig::default();
+    let id = Oid::from_str("c08f514e6b1fd2d1bb1a7bdc5b5b1b4e7f1c2a3d").unwrap();
+
+    let mut cache = CommitCache::load(dir, &config);
+    assert!(cache.commit(id).is_none());
+
+    cache.insert_commit(
+        id,
+        CachedCommit {
+            changed_packages: HashSet::from(["dioxus-core".to_string()]),
+            churn: ChurnStats {
+                insertions: 10,
+                deletions: 2,
+                files_changed: 1,
+            },
+            ..Default::default()
+        },
+    );
+    cache.save().unwrap();
+
+    let cache = CommitCache::load(dir, &config);
+    let cached = cache.commit(id).unwrap();
+    assert!(cached.changed_packages.contains("dioxus-core"));
+    assert_eq!(cached.churn.lines(), 12);
+
+    let mut changed = config.clone();
+    changed.churn.exclude.push("**/*.md".to_string());
+    assert!(CommitCache::load(dir, &changed).commit(id).is_none());
+}
@@ -0,0 +1,100 @@
+//! Read published versions out of a local copy of the crates.io index
+//!
+//! This works with both a git checkout of `crates.io-index` and cargo's sparse index cache under
+//! `~/.cargo/registry/index/*/.cache`, so it never needs to hit the network.
+
+use std::path::{Path, PathBuf};
+
+use semver::Version;
+use serde::Deserialize;
+
+/// A single line of an index file
+#[derive(Debug, Deserialize)]
+struct IndexEntry {
+    vers: String,
+
+    #[serde(default)]
+    yanked: bool,
+}
+
+/// A local crates.io index, either a git checkout or a sparse cache
+pub struct CratesIndex {
+    root: PathBuf,
+}
+
+impl CratesIndex {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The newest version of a crate that hasn't been yanked, or `None` if it was never published
+    pub fn newest_version(&self, name: &str) -> Option<Version> {
+        let contents = std::fs::read(self.root.join(index_path(name))).ok()?;
+
+        parse_entries(&contents)
+            .into_iter()
+            .filter(|entry| !entry.yanked)
+            .filter_map(|entry| Version::parse(&entry.vers).ok())
+            .max()
+    }
+}
+
+/// The path of a crate's file in the index
+///
+/// See <https://doc.rust-lang.org/cargo/reference/registry-index.html#index-files>
+fn index_path(name: &str) -> PathBuf {
+    let name = name.to_lowercase();
+
+    match name.len() {
+        1 => Path::new("1").join(&name),
+        2 => Path::new("2").join(&name),
+        3 => Path::new("3").join(&name[..1]).join(&name),
+        _ => Path::new(&name[..2]).join(&name[2..4]).join(&name),
+    }
+}
+
+/// Parse the entries of an index file
+///
+/// A git checkout has one json object per line. The sparse cache has a small binary header and then
+/// alternates between a version and its json object, all separated by null bytes. Either way, every
+/// chunk that looks like json is an entry.
+fn parse_entries(contents: &[u8]) -> Vec<IndexEntry> {
+    contents
+        .split(|&byte| byte == b'\n' || byte == 0)
+        .filter(|chunk| chunk.first() == Some(&b'{'))
+        .filter_map(|chunk| serde_json::from_slice(chunk).ok())
+        .collect()
+}
+
+#[test]
+fn reads_git_and_sparse_index_files() {
+    assert_eq!(index_path("a"), PathBuf::from("1/a"));
+    assert_eq!(index_path("cc"), PathBuf::from("2/cc"));
+    assert_eq!(index_path("syn"), PathBuf::from("3/s/syn"));
+    assert_eq!(index_path("Dioxus"), PathBuf::from("di/ox/dioxus"));
+
+    let git = br#"{"name":"dioxus","vers":"0.5.0","yanked":false}
+{"name":"dioxus","vers":"0.5.1","yanked":false}
+{"name":"dioxus","vers":"0.5.2","yanked":true}"#;
+
+    let versions: Vec<String> = parse_entries(git)
+        .into_iter()
+        .map(|entry| entry.vers)
+        .collect();
+    assert_eq!(versions, vec!["0.5.0", "0.5.1", "0.5.2"]);
+
+    let mut sparse = vec![3, 2, 0, 0, 0];
+    sparse.extend_from_slice(b"etag: \"abc\"\x000.5.0\x00");
+    sparse.extend_from_slice(br#"{"name":"dioxus","vers":"0.5.0","yanked":false}"#);
+    sparse.extend_from_slice(b"\x000.5.1\x00");
+    sparse.extend_from_slice(br#"{"name":"dioxus","vers":"0.5.1","yanked":false}"#);
+    sparse.push(0);
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("di/ox")).unwrap();
+    std::fs::write(dir.path().join("di/ox/dioxus"), &sparse).unwrap();
+
+    let index = CratesIndex::new(dir.path());
+    assert_eq!(index.newest_version("dioxus"), Some(Version::new(0, 5, 1)));
+    assert_eq!(index.newest_version("dioxus-not-published"), None);
+}
@@ -0,0 +1,152 @@
+//! The errors the collectors can run into
+//!
+//! Most failures only affect a single repo or commit. With `--keep-going` those get recorded as a
+//! [`Diagnostic`] in the blob that's being written and the run carries on with the rest, otherwise the
+//! first one ends the run.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use doxie_types::Diagnostic;
+
+pub type Result<T, E = DoxieError> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum DoxieError {
+    #[error("git: {0}")]
+    Git(#[from] git2::Error),
+
+    /// Boxed since octocrab's errors are much bigger than everything else here
+    #[error("github: {0}")]
+    GitHub(Box<octocrab::Error>),
+
+    #[error("failed to {action} {}: {source}", path.display())]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("json: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("failed to parse {}: {source}", path.display())]
+    Config {
+        path: PathBuf,
+        source: Box<toml::de::Error>,
+    },
+
+    #[error("`{command}` failed: {reason}")]
+    Command { command: String, reason: String },
+
+    #[error("invalid wasm: {0}")]
+    Wasm(#[from] wasmparser::BinaryReaderError),
+
+    #[error("couldn't check out {0} into a worktree")]
+    Worktree(String),
+
+    #[error("the baseline {0} is the commit being benchmarked")]
+    SameBaseline(String),
+
+    #[error("couldn't find the commit {krate} {version} was published from")]
+    UnknownRelease { krate: String, version: String },
+
+    #[error("{version} is already tagged as {picked}")]
+    AmbiguousTag { version: String, picked: String },
+
+    #[error("`{0}` is not in the form of owner/repo")]
+    InvalidRepo(String),
+
+    #[error("{0} isn't in the github fixture")]
+    MissingFixture(String),
+}
+
+impl From<doxie_types::InvalidRepo> for DoxieError {
+    fn from(err: doxie_types::InvalidRepo) -> Self {
+        Self::InvalidRepo(err.0)
+    }
+}
+
+impl From<octocrab::Error> for DoxieError {
+    fn from(err: octocrab::Error) -> Self {
+        Self::GitHub(Box::new(err))
+    }
+}
+
+impl DoxieError {
+    /// Wrap an io error with the file it happened on, for use with `map_err`
+    pub fn io<'a>(
+        action: &'static str,
+        path: &'a Path,
+    ) -> impl FnOnce(std::io::Error) -> Self + 'a {
+        move |source| Self::Io {
+            action,
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+/// The failures a collector recovered from, which end up in its output blob
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    keep_going: bool,
+    entries: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            entries: vec![],
+        }
+    }
+
+    /// Record the error and hand back `None` when we're keeping going, otherwise pass it up
+    pub fn recover<T>(&mut self, scope: impl Display, result: Result<T>) -> Result<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if self.keep_going => {
+                self.record(scope, err);
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Record the error whether or not we're keeping going, for failures that should never end the run
+    pub fn record(&mut self, scope: impl Display, err: DoxieError) {
+        eprintln!("Skipping {scope}: {err}");
+        self.entries.push(Diagnostic {
+            scope: scope.to_string(),
+            message: err.to_string(),
+        });
+    }
+
+    pub fn into_entries(self) -> Vec<Diagnostic> {
+        self.entries
+    }
+}
+
+#[test]
+fn records_errors_only_when_keeping_going() {
+    let failure = || -> Result<()> { Err(DoxieError::InvalidRepo("dioxus".to_string())) };
+
+    let mut strict = Diagnostics::new(false);
+    assert!(strict.recover("repo dioxus", failure()).is_err());
+    assert_eq!(strict.recover("repo dioxus", Ok(1)).unwrap(), Some(1));
+    assert!(strict.into_entries().is_empty());
+
+    let mut lenient = Diagnostics::new(true);
+    assert_eq!(lenient.recover("repo dioxus", failure()).unwrap(), None);
+
+    let entries = lenient.into_entries();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].scope, "repo dioxus");
+    assert_eq!(
+        entries[0].message,
+        "`dioxus` is not in the form of owner/repo"
+    );
+}
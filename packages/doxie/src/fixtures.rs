@@ -0,0 +1,347 @@
+//! Throwaway git repos for the tests
+//!
+//! The collectors only ever look at the object database, so the fixtures write blobs, trees and commits
+//! straight into a fresh repo without touching a working tree. Every commit gets a fixed author and a
+//! timestamp that only depends on the order it was made in, so the shas - and the snapshots that contain
+//! them - are the same on every machine.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use doxie_types::OpenPrMap;
+use git2::{Oid, Repository, RepositoryInitOptions, Signature, Time, Tree};
+use serde_json::Value;
+use tempfile::TempDir;
+
+use crate::github::FixtureGitHub;
+
+/// The time of the first commit, everything after it is a minute apart
+const EPOCH: i64 = 1_700_000_000;
+
+pub struct FixtureRepo {
+    pub repo: Repository,
+
+    /// The repo gets deleted once the fixture is dropped
+    _dir: TempDir,
+    commits: i64,
+}
+
+impl FixtureRepo {
+    /// An empty repo whose HEAD points at `main`
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_opts(
+            dir.path(),
+            RepositoryInitOptions::new().initial_head("main"),
+        )
+        .unwrap();
+
+        Self {
+            repo,
+            _dir: dir,
+            commits: 0,
+        }
+    }
+
+    /// Commit the files on top of the tip of a branch, creating the branch if it doesn't exist yet
+    ///
+    /// Files are given as `(path, contents)`, anything not listed is carried over from the parent
+    pub fn commit(&mut self, branch: &str, message: &str, files: &[(&str, &str)]) -> Oid {
+        let parent = self.tip(branch);
+        let tree = {
+            let base = parent.map(|id| self.repo.find_commit(id).unwrap().tree().unwrap());
+            write_tree(&self.repo, base.as_ref(), files)
+        };
+
+        self.write_commit(branch, message, tree, parent.into_iter().collect())
+    }
+
+    /// Merge one branch into another with a merge commit, like the github merge button does
+    pub fn merge(&mut self, into: &str, from: &str, message: &str) -> Oid {
+        let (ours, theirs) = (self.tip(into).unwrap(), self.tip(from).unwrap());
+
+        let tree = self
+            .repo
+            .merge_commits(
+                &self.repo.find_commit(ours).unwrap(),
+                &self.repo.find_commit(theirs).unwrap(),
+                None,
+            )
+            .unwrap()
+            .write_tree_to(&self.repo)
+            .unwrap();
+
+        self.write_commit(into, message, tree, vec![ours, theirs])
+    }
+
+    pub fn branch(&self, name: &str, at: Oid) {
+        let commit = self.repo.find_commit(at).unwrap();
+        self.repo.branch(name, &commit, false).unwrap();
+    }
+
+    /// A lightweight tag, which is just a ref to the commit
+    pub fn tag(&self, name: &str, at: Oid) {
+        let object = self.repo.find_object(at, None).unwrap();
+        self.repo.tag_lightweight(name, &object, false).unwrap();
+    }
+
+    /// An annotated tag, which points at a tag object instead of the commit
+    pub fn annotated_tag(&self, name: &str, at: Oid) {
+        let object = self.repo.find_object(at, None).unwrap();
+        let tagger = self.signature(self.commits);
+        self.repo
+            .tag(name, &object, &tagger, &format!("Release {name}"), false)
+            .unwrap();
+    }
+
+    pub fn tip(&self, branch: &str) -> Option<Oid> {
+        self.repo
+            .find_reference(&format!("refs/heads/{branch}"))
+            .ok()
+            .and_then(|reference| reference.target())
+    }
+
+    fn write_commit(&mut self, branch: &str, message: &str, tree: Oid, parents: Vec<Oid>) -> Oid {
+        let signature = self.signature(self.commits);
+        self.commits += 1;
+
+        let tree = self.repo.find_tree(tree).unwrap();
+        let parents: Vec<_> = parents
+            .into_iter()
+            .map(|id| self.repo.find_commit(id).unwrap())
+            .collect();
+
+        self.repo
+            .commit(
+                Some(&format!("refs/heads/{branch}")),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents.iter().collect::<Vec<_>>(),
+            )
+            .unwrap()
+    }
+
+    fn signature(&self, offset: i64) -> Signature<'static> {
+        Signature::new(
+            "Doxie",
+            "doxie@dioxuslabs.com",
+            &Time::new(EPOCH + offset * 60, 0),
+        )
+        .unwrap()
+    }
+}
+
+/// Write the files into a copy of the base tree, creating the folders along the way
+fn write_tree(repo: &Repository, base: Option<&Tree>, files: &[(&str, &str)]) -> Oid {
+    let mut builder = repo.treebuilder(base).unwrap();
+    let mut nested: BTreeMap<&str, Vec<(&str, &str)>> = BTreeMap::new();
+
+    for &(path, contents) in files {
+        match path.split_once('/') {
+            Some((dir, rest)) => nested.entry(dir).or_default().push((rest, contents)),
+            None => {
+                let blob = repo.blob(contents.as_bytes()).unwrap();
+                builder.insert(path, blob, 0o100644).unwrap();
+            }
+        }
+    }
+
+    for (dir, files) in nested {
+        let subtree = base
+            .and_then(|tree| tree.get_name(dir))
+            .and_then(|entry| entry.to_object(repo).ok())
+            .and_then(|object| object.into_tree().ok());
+
+        let id = write_tree(repo, subtree.as_ref(), &files);
+        builder.insert(dir, id, 0o040000).unwrap();
+    }
+
+    builder.write().unwrap()
+}
+
+/// A small workspace with the kinds of history the dioxus repo has
+///
+/// - `dioxus-web` depends on `dioxus-html`, which depends on `dioxus-core`
+/// - `v0.4.0` is annotated, the other tags are lightweight
+/// - PRs land as squash commits, except #102 which is a merge commit
+/// - `v0.5` is a stable branch with #103 cherry-picked onto it and released as `v0.5.1`
+/// - main moved on to `v0.6.0-alpha.0`, with #105 landing after it
+pub fn dioxus_like() -> FixtureRepo {
+    let mut fixture = dioxus_before_prerelease();
+
+    let release = fixture.commit(
+        "main",
+        "Release 0.6.0-alpha.0",
+        &[("Cargo.toml", &root_manifest("0.6.0-alpha.0"))],
+    );
+    fixture.tag("v0.6.0-alpha.0", release);
+
+    fixture.commit(
+        "main",
+        "Add a web-sys feature (#105)",
+        &[(
+            "packages/web/src/lib.rs",
+            "pub fn launch() {\n    // don't panic without a root element\n}\n\npub fn web_sys() {}\n",
+        )],
+    );
+
+    fixture
+}
+
+/// [`dioxus_like`] before the prerelease, so the newest tag is `v0.5.1` on the stable branch and main
+/// only has #104 on top of where the branch started
+pub fn dioxus_before_prerelease() -> FixtureRepo {
+    let mut fixture = FixtureRepo::new();
+
+    let init = fixture.commit(
+        "main",
+        "Initial commit",
+        &[
+            ("Cargo.toml", &root_manifest("0.4.0")),
+            ("Cargo.lock", "# the lockfile\n"),
+            (
+                "packages/core/Cargo.toml",
+                &crate_manifest("dioxus-core", &[]),
+            ),
+            ("packages/core/src/lib.rs", "pub fn diff() {}\n"),
+            (
+                "packages/html/Cargo.toml",
+                &crate_manifest("dioxus-html", &["dioxus-core"]),
+            ),
+            ("packages/html/src/lib.rs", "pub fn div() {}\n"),
+            (
+                "packages/web/Cargo.toml",
+                &crate_manifest("dioxus-web", &["dioxus-html"]),
+            ),
+            ("packages/web/src/lib.rs", "pub fn launch() {}\n"),
+        ],
+    );
+    fixture.annotated_tag("v0.4.0", init);
+
+    fixture.commit(
+        "main",
+        "Fix the diffing of keyed lists (#101)",
+        &[(
+            "packages/core/src/lib.rs",
+            "pub fn diff() {\n    keyed();\n}\n\nfn keyed() {}\n",
+        )],
+    );
+    let release = fixture.commit(
+        "main",
+        "Release 0.4.1",
+        &[("Cargo.toml", &root_manifest("0.4.1"))],
+    );
+    fixture.tag("v0.4.1", release);
+
+    fixture.branch("onmounted", release);
+    fixture.commit(
+        "onmounted",
+        "Add onmounted to the html elements",
+        &[(
+            "packages/html/src/lib.rs",
+            "pub fn div() {}\n\npub fn onmounted() {}\n",
+        )],
+    );
+    fixture.merge(
+        "main",
+        "onmounted",
+        "Merge pull request #102 from ealmloff/onmounted\n\nAdd onmounted to the html elements",
+    );
+    let release = fixture.commit(
+        "main",
+        "Release 0.5.0",
+        &[("Cargo.toml", &root_manifest("0.5.0"))],
+    );
+    fixture.tag("v0.5.0", release);
+    fixture.branch("v0.5", release);
+
+    let web_fix = [(
+        "packages/web/src/lib.rs",
+        "pub fn launch() {\n    // don't panic without a root element\n}\n",
+    )];
+    let fix = fixture.commit(
+        "main",
+        "Fix a panic in the web renderer (#103)\n\nFixes #90",
+        &web_fix,
+    );
+    fixture.commit(
+        "v0.5",
+        &format!("Fix a panic in the web renderer (#103)\n\n(cherry picked from commit {fix})"),
+        &web_fix,
+    );
+    let release = fixture.commit(
+        "v0.5",
+        "Release 0.5.1",
+        &[("Cargo.toml", &root_manifest("0.5.1"))],
+    );
+    fixture.tag("v0.5.1", release);
+
+    fixture.commit(
+        "main",
+        "Rewrite the core scheduler (#104)\n\nCloses dioxuslabs/blitz#12\n\nCo-authored-by: Evan Almloff <evan@dioxuslabs.com>",
+        &[
+            ("packages/core/src/lib.rs", "pub fn diff() {}\n\npub fn schedule() {}\n"),
+            ("Cargo.lock", "# the lockfile\n# with a new dependency\n"),
+        ],
+    );
+
+    fixture
+}
+
+fn root_manifest(version: &str) -> String {
+    format!(
+        "[workspace]\nmembers = [\"packages/*\"]\n\n[workspace.package]\nversion = \"{version}\"\n"
+    )
+}
+
+fn crate_manifest(name: &str, dependencies: &[&str]) -> String {
+    let mut manifest =
+        format!("[package]\nname = \"{name}\"\nversion.workspace = true\n\n[dependencies]\n");
+
+    for dependency in dependencies {
+        manifest.push_str(&format!("{dependency} = {{ workspace = true }}\n"));
+    }
+
+    manifest
+}
+
+/// A github that answers with the open PRs saved in `data/open_prs.json`
+pub fn github() -> FixtureGitHub {
+    FixtureGitHub::new(open_prs())
+}
+
+/// The open PRs saved in `data/open_prs.json`, for tests that need to add their own
+pub fn open_prs() -> OpenPrMap {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../data/open_prs.json");
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+/// Read a json blob the collectors wrote, ready to be snapshotted
+///
+/// Going through `Value` sorts the keys of every map, and the fields that come out of a `HashSet` get
+/// sorted here so their order doesn't change between runs
+pub fn read_blob(path: impl Into<PathBuf>) -> Value {
+    let raw = std::fs::read_to_string(path.into()).unwrap();
+    let mut value = serde_json::from_str(&raw).unwrap();
+    sort_sets(&mut value);
+    value
+}
+
+fn sort_sets(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if let ("changed_packages" | "affected_packages", Value::Array(items)) =
+                    (key.as_str(), &mut *value)
+                {
+                    items.sort_by_key(|item| item.to_string());
+                }
+
+                sort_sets(value);
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(sort_sets),
+        _ => {}
+    }
+}
@@ -0,0 +1,517 @@
+//! Everything the bot asks github for
+//!
+//! The collectors only talk to github through the [`GitHub`] trait. In a workflow that's the octocrab
+//! client, but it can also be a [`FixtureGitHub`] that answers from a saved `open_prs.json`, which is
+//! what the tests use and what lets the bot run locally without a token or network. Wrapping either in
+//! [`DryRun`] prints the comments the bot would write instead of posting them.
+//!
+//! The octocrab client makes every read conditional on the [`ResponseCache`] from the last run and
+//! paces itself with a [`RateLimit`], so a run that finds nothing new costs next to nothing.
+
+use std::{collections::HashMap, future::Future, path::Path, sync::Mutex};
+
+use async_trait::async_trait;
+use doxie_types::{GitHubConfig, OpenPrMap, OpenPrs};
+use http::{header::LINK, HeaderMap, StatusCode};
+use octocrab::{
+    models::{issues::Issue, pulls::PullRequest, CommentId},
+    Octocrab,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+
+use crate::{
+    error::{DoxieError, Result},
+    rate_limit::{self, RateLimit, Usage},
+    response_cache::{CachedResponse, ResponseCache},
+};
+
+/// How many times we back off and retry a request github refused for going over a limit
+const MAX_RETRIES: usize = 3;
+
+/// The first items of a listing and how many there are in total
+#[derive(Debug, Clone)]
+pub struct Listing<T> {
+    pub items: Vec<T>,
+
+    /// More than `items.len()` if the listing was cut off at `max_items`
+    pub total_count: usize,
+}
+
+/// A comment on an issue or PR
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub id: u64,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait GitHub: Send + Sync {
+    async fn open_prs(&self, owner: &str, repo: &str) -> Result<Listing<PullRequest>>;
+
+    async fn pull(&self, owner: &str, repo: &str, number: u64) -> Result<PullRequest>;
+
+    /// The names of the labels on an issue or PR
+    async fn labels(&self, owner: &str, repo: &str, number: u64) -> Result<Vec<String>>;
+
+    /// The raw events of an issue or PR's timeline
+    async fn timeline(&self, owner: &str, repo: &str, number: u64) -> Result<Value>;
+
+    async fn comments(&self, owner: &str, repo: &str, number: u64) -> Result<Vec<Comment>>;
+
+    async fn create_comment(&self, owner: &str, repo: &str, number: u64, body: &str) -> Result<()>;
+
+    async fn update_comment(&self, owner: &str, repo: &str, id: u64, body: &str) -> Result<()>;
+
+    /// Count the requests made from here on towards a collector, for the summary at the end of the run
+    fn start_collector(&self, _collector: &'static str) {}
+
+    /// Save anything worth keeping for the next run and report what this one cost
+    fn finish(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The real github api, through octocrab
+pub struct Client {
+    octocrab: Octocrab,
+    max_items: usize,
+    cache: Mutex<ResponseCache>,
+    rate_limit: Mutex<RateLimit>,
+    usage: Mutex<Usage>,
+}
+
+impl Client {
+    /// A client that keeps its [`ResponseCache`] in the output dir
+    pub fn new(octocrab: Octocrab, config: &GitHubConfig, out_dir: &Path) -> Self {
+        Self {
+            octocrab,
+            max_items: config.max_items,
+            cache: Mutex::new(ResponseCache::load(out_dir)),
+            rate_limit: Mutex::new(RateLimit::new(config.rate_limit_reserve)),
+            usage: Mutex::default(),
+        }
+    }
+
+    /// Read every page of a listing, up to `max_items`
+    async fn list<T: DeserializeOwned>(&self, route: String) -> Result<Pages<T>> {
+        paginate(route, self.max_items, |url| async move {
+            self.get::<Vec<T>>(&url).await
+        })
+        .await
+    }
+
+    /// Send a conditional `GET`, answering from the cache if github says nothing changed
+    ///
+    /// Hands back the parsed body and the url of the next page, if there is one
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<(T, Option<String>)> {
+        let cached = self.cache.lock().unwrap().get(url).cloned();
+        let headers = cached.as_ref().map(CachedResponse::conditional_headers);
+
+        let mut retries = 0;
+        let response = loop {
+            let pause = self.rate_limit.lock().unwrap().pause(rate_limit::now());
+            if let Some(pause) = pause {
+                self.wait(pause).await;
+            }
+
+            let response = self
+                .octocrab
+                ._get_with_headers(url, headers.clone())
+                .await?;
+            let (status, headers) = (response.status(), response.headers());
+
+            self.usage
+                .lock()
+                .unwrap()
+                .record_request(status == StatusCode::NOT_MODIFIED);
+            self.rate_limit.lock().unwrap().update(headers);
+
+            match RateLimit::backoff(status, headers, rate_limit::now()) {
+                Some(backoff) if retries < MAX_RETRIES => {
+                    retries += 1;
+                    self.wait(backoff).await;
+                }
+                _ => break response,
+            }
+        };
+
+        let fresh = match (response.status(), cached) {
+            (StatusCode::NOT_MODIFIED, Some(cached)) => cached,
+            _ => {
+                let response = octocrab::map_github_error(response).await?;
+                let headers = response.headers().clone();
+                let body = self.octocrab.body_to_string(response).await?;
+                let next = next_link(&headers);
+
+                match CachedResponse::new(&headers, body.clone(), next.clone()) {
+                    Some(fresh) => {
+                        self.cache.lock().unwrap().insert(url, fresh.clone());
+                        fresh
+                    }
+                    None => CachedResponse {
+                        etag: None,
+                        last_modified: None,
+                        body,
+                        next,
+                    },
+                }
+            }
+        };
+
+        Ok((serde_json::from_str(&fresh.body)?, fresh.next))
+    }
+
+    async fn wait(&self, duration: std::time::Duration) {
+        println!("Waiting {}s for the github rate limit", duration.as_secs());
+        self.usage.lock().unwrap().record_wait(duration);
+        tokio::time::sleep(duration).await;
+    }
+
+    /// Writes aren't cached, but they still come out of the budget
+    fn record_write(&self) {
+        self.usage.lock().unwrap().record_request(false);
+    }
+}
+
+/// The url of the next page of a listing, from a `Link` header like `<url>; rel="next", <url>; rel="last"`
+fn next_link(headers: &HeaderMap) -> Option<String> {
+    let links = headers.get(LINK)?.to_str().ok()?;
+
+    links.split(',').find_map(|link| {
+        let (url, params) = link.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#)
+            .then(|| {
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+    })
+}
+
+/// The only part of a search we look at
+#[derive(Deserialize)]
+struct SearchCount {
+    total_count: usize,
+}
+
+#[async_trait]
+impl GitHub for Client {
+    async fn open_prs(&self, owner: &str, repo: &str) -> Result<Listing<PullRequest>> {
+        let route = format!("/repos/{owner}/{repo}/pulls?state=open&per_page=100");
+        let pages = self.list(route).await?;
+
+        // The listing doesn't say how long it is, but the search does
+        let total_count = match pages.capped {
+            false => pages.items.len(),
+            true => {
+                let route =
+                    format!("/search/issues?q=repo:{owner}/{repo}+is:pr+is:open&per_page=1");
+                let (search, _) = self.get::<SearchCount>(&route).await?;
+                search.total_count
+            }
+        };
+
+        Ok(Listing {
+            items: pages.items,
+            total_count,
+        })
+    }
+
+    async fn pull(&self, owner: &str, repo: &str, number: u64) -> Result<PullRequest> {
+        let route = format!("/repos/{owner}/{repo}/pulls/{number}");
+        Ok(self.get(&route).await?.0)
+    }
+
+    async fn labels(&self, owner: &str, repo: &str, number: u64) -> Result<Vec<String>> {
+        let route = format!("/repos/{owner}/{repo}/issues/{number}");
+        let (issue, _) = self.get::<Issue>(&route).await?;
+        Ok(issue.labels.into_iter().map(|label| label.name).collect())
+    }
+
+    async fn timeline(&self, owner: &str, repo: &str, number: u64) -> Result<Value> {
+        let route = format!("/repos/{owner}/{repo}/issues/{number}/timeline?per_page=100");
+        let pages = self.list(route).await?;
+        Ok(Value::Array(pages.items))
+    }
+
+    async fn comments(&self, owner: &str, repo: &str, number: u64) -> Result<Vec<Comment>> {
+        let route = format!("/repos/{owner}/{repo}/issues/{number}/comments?per_page=100");
+        let pages = self
+            .list::<octocrab::models::issues::Comment>(route)
+            .await?;
+
+        Ok(pages
+            .items
+            .into_iter()
+            .map(|comment| Comment {
+                id: comment.id.into_inner(),
+                body: comment.body.unwrap_or_default(),
+            })
+            .collect())
+    }
+
+    async fn create_comment(&self, owner: &str, repo: &str, number: u64, body: &str) -> Result<()> {
+        self.record_write();
+        self.octocrab
+            .issues(owner, repo)
+            .create_comment(number, body)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_comment(&self, owner: &str, repo: &str, id: u64, body: &str) -> Result<()> {
+        self.record_write();
+        self.octocrab
+            .issues(owner, repo)
+            .update_comment(CommentId(id), body)
+            .await?;
+        Ok(())
+    }
+
+    fn start_collector(&self, collector: &'static str) {
+        self.usage.lock().unwrap().start(collector);
+    }
+
+    fn finish(&self) -> Result<()> {
+        print!("{}", self.usage.lock().unwrap().summary());
+        self.cache.lock().unwrap().save()
+    }
+}
+
+/// The items of a listing that we got through [`paginate`]
+struct Pages<T> {
+    items: Vec<T>,
+
+    /// Whether we stopped at `max_items` while there were still more pages
+    capped: bool,
+}
+
+/// Follow the `next` links of a listing until they run out or we have `max_items`
+///
+/// `fetch` gets the url of a page and hands back its items and the url of the page after it
+async fn paginate<T, F, Fut>(first: String, max_items: usize, mut fetch: F) -> Result<Pages<T>>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<(Vec<T>, Option<String>)>>,
+{
+    let mut items = vec![];
+    let mut next = Some(first);
+
+    while let Some(url) = next.take() {
+        if items.len() >= max_items {
+            next = Some(url);
+            break;
+        }
+
+        let (page, after) = fetch(url).await?;
+        items.extend(page);
+        next = after;
+    }
+
+    let capped = next.is_some() || items.len() > max_items;
+    items.truncate(max_items);
+
+    Ok(Pages { items, capped })
+}
+
+/// Answers from a saved `open_prs.json` instead of the api
+///
+/// Comments are kept in memory so a test can check what was written. PRs have no timeline events.
+pub struct FixtureGitHub {
+    open_prs: OpenPrMap,
+    comments: Mutex<HashMap<(String, u64), Vec<Comment>>>,
+}
+
+impl FixtureGitHub {
+    pub fn new(open_prs: OpenPrMap) -> Self {
+        Self {
+            open_prs,
+            comments: Mutex::default(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = std::fs::read_to_string(path).map_err(DoxieError::io("read", path))?;
+        Ok(Self::new(serde_json::from_str(&raw)?))
+    }
+
+    fn prs(&self, owner: &str, repo: &str) -> Result<&OpenPrs> {
+        // The blob is keyed by the name of the repo without its owner
+        self.open_prs
+            .prs
+            .get(repo)
+            .ok_or_else(|| DoxieError::MissingFixture(format!("{owner}/{repo}")))
+    }
+}
+
+#[async_trait]
+impl GitHub for FixtureGitHub {
+    async fn open_prs(&self, owner: &str, repo: &str) -> Result<Listing<PullRequest>> {
+        let prs = self.prs(owner, repo)?;
+
+        Ok(Listing {
+            items: prs.prs.clone(),
+            total_count: prs.total_count.max(prs.prs.len()),
+        })
+    }
+
+    async fn pull(&self, owner: &str, repo: &str, number: u64) -> Result<PullRequest> {
+        self.prs(owner, repo)?
+            .prs
+            .iter()
+            .find(|pr| pr.number == number)
+            .cloned()
+            .ok_or_else(|| DoxieError::MissingFixture(format!("{owner}/{repo}#{number}")))
+    }
+
+    async fn labels(&self, owner: &str, repo: &str, number: u64) -> Result<Vec<String>> {
+        let pr = self.pull(owner, repo, number).await?;
+        Ok(pr
+            .labels
+            .unwrap_or_default()
+            .into_iter()
+            .map(|label| label.name)
+            .collect())
+    }
+
+    async fn timeline(&self, _owner: &str, _repo: &str, _number: u64) -> Result<Value> {
+        Ok(Value::Array(vec![]))
+    }
+
+    async fn comments(&self, _owner: &str, repo: &str, number: u64) -> Result<Vec<Comment>> {
+        let comments = self.comments.lock().unwrap();
+        Ok(comments
+            .get(&(repo.to_string(), number))
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn create_comment(
+        &self,
+        _owner: &str,
+        repo: &str,
+        number: u64,
+        body: &str,
+    ) -> Result<()> {
+        let mut comments = self.comments.lock().unwrap();
+        let id = comments.values().map(Vec::len).sum::<usize>() as u64 + 1;
+
+        comments
+            .entry((repo.to_string(), number))
+            .or_default()
+            .push(Comment {
+                id,
+                body: body.to_string(),
+            });
+
+        Ok(())
+    }
+
+    async fn update_comment(&self, _owner: &str, repo: &str, id: u64, body: &str) -> Result<()> {
+        let mut comments = self.comments.lock().unwrap();
+
+        let comment = comments
+            .iter_mut()
+            .filter(|((other, _), _)| other == repo)
+            .flat_map(|(_, comments)| comments.iter_mut())
+            .find(|comment| comment.id == id)
+            .ok_or_else(|| DoxieError::MissingFixture(format!("comment {id} on {repo}")))?;
+
+        comment.body = body.to_string();
+        Ok(())
+    }
+}
+
+/// Reads go through to the real client, but writes are printed instead of sent
+pub struct DryRun(pub Box<dyn GitHub>);
+
+#[async_trait]
+impl GitHub for DryRun {
+    async fn open_prs(&self, owner: &str, repo: &str) -> Result<Listing<PullRequest>> {
+        self.0.open_prs(owner, repo).await
+    }
+
+    async fn pull(&self, owner: &str, repo: &str, number: u64) -> Result<PullRequest> {
+        self.0.pull(owner, repo, number).await
+    }
+
+    async fn labels(&self, owner: &str, repo: &str, number: u64) -> Result<Vec<String>> {
+        self.0.labels(owner, repo, number).await
+    }
+
+    async fn timeline(&self, owner: &str, repo: &str, number: u64) -> Result<Value> {
+        self.0.timeline(owner, repo, number).await
+    }
+
+    async fn comments(&self, owner: &str, repo: &str, number: u64) -> Result<Vec<Comment>> {
+        self.0.comments(owner, repo, number).await
+    }
+
+    async fn create_comment(&self, owner: &str, repo: &str, number: u64, body: &str) -> Result<()> {
+        println!("Would comment on {owner}/{repo}#{number}:\n\n{body}");
+        Ok(())
+    }
+
+    async fn update_comment(&self, owner: &str, repo: &str, id: u64, body: &str) -> Result<()> {
+        println!("Would update comment {id} on {owner}/{repo}:\n\n{body}");
+        Ok(())
+    }
+
+    fn start_collector(&self, collector: &'static str) {
+        self.0.start_collector(collector)
+    }
+
+    fn finish(&self) -> Result<()> {
+        self.0.finish()
+    }
+}
+
+#[test]
+fn finds_the_next_page() {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        LINK,
+        r#"<https://api.github.com/repositories/1/pulls?page=3>; rel="next", <https://api.github.com/repositories/1/pulls?page=9>; rel="last""#
+            .parse()
+            .unwrap(),
+    );
+    assert_eq!(
+        next_link(&headers).as_deref(),
+        Some("https://api.github.com/repositories/1/pulls?page=3")
+    );
+
+    headers.insert(
+        LINK,
+        r#"<https://api.github.com/repositories/1/pulls?page=1>; rel="first""#
+            .parse()
+            .unwrap(),
+    );
+    assert_eq!(next_link(&headers), None);
+}
+
+#[tokio::test]
+async fn follows_pages_up_to_the_cap() {
+    // Three pages of two items each
+    let fetch = |url: String| async move {
+        let page: usize = url.parse().unwrap();
+        let next = (page < 3).then(|| (page + 1).to_string());
+        Ok((vec![page * 10, page * 10 + 1], next))
+    };
+
+    let all = paginate("1".to_string(), 100, fetch).await.unwrap();
+    assert_eq!(all.items, [10, 11, 20, 21, 30, 31]);
+    assert!(!all.capped);
+
+    let exact = paginate("1".to_string(), 6, fetch).await.unwrap();
+    assert_eq!(exact.items.len(), 6);
+    assert!(!exact.capped);
+
+    // Stops asking for pages once it has enough, and cuts off the extra items of the last page
+    let capped = paginate("1".to_string(), 3, fetch).await.unwrap();
+    assert_eq!(capped.items, [10, 11, 20]);
+    assert!(capped.capped);
+}
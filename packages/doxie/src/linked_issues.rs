@@ -0,0 +1,245 @@
+//! Connect PRs to the issues they fix
+//!
+//! The cheap source is the text itself - closing keywords like `fixes #123` in the commit message or the
+//! PR description. A bare `#123` could just as well be another PR ("reverts #1200"), and the text can't
+//! tell the two apart. Issues that mention a PR show up in the timeline API instead, which knows what's an
+//! issue, so we also read that when we have a token to spare the requests.
+
+use doxie_types::*;
+use serde_json::Value;
+
+use crate::{commit_cache::CommitCache, error::Result, github::GitHub, pr_refs};
+
+/// Find the issues some text closes, ie a commit message or the title and body of a PR
+///
+/// Only references after a closing keyword count, since plain mentions are as often PRs as issues.
+/// References without an `owner/repo` point into `repo`.
+pub fn from_text(repo: &str, text: &str) -> Vec<LinkedIssue> {
+    let mut issues = vec![];
+
+    for closed in pr_refs::parse(text).closes {
+        let issue = LinkedIssue {
+            repo: closed.repo.unwrap_or_else(|| repo.to_string()),
+            number: closed.number,
+            closes: true,
+        };
+        merge(&mut issues, issue);
+    }
+
+    issues
+}
+
+/// Find the issues that reference a PR through its timeline
+///
+/// Any failures are logged and skipped since the text references are usually good enough
+pub async fn from_timeline(
+    github: &dyn GitHub,
+    owner: &str,
+    repo: &str,
+    number: u64,
+) -> Vec<LinkedIssue> {
+    fetch_timeline(github, owner, repo, number)
+        .await
+        .unwrap_or_default()
+}
+
+/// Add the issues from the timeline of every PR to its commit
+///
+/// The timelines of released PRs are read from the cache once they've been fetched, so only the
+/// unreleased ones cost a request on every run
+pub async fn add_timeline(
+    github: &dyn GitHub,
+    config: &DoxieConfig,
+    commits: &mut [PrCommit],
+    cache: &mut CommitCache,
+    released: bool,
+) -> Result<()> {
+    let (owner, repo) = config.main_owner_and_repo()?;
+
+    for commit in commits.iter_mut() {
+        let Some(id) = commit.id else {
+            continue;
+        };
+
+        let key = format!("{owner}/{repo}#{id}");
+        let issues = match cache.timeline(&key) {
+            Some(cached) if released => cached.clone(),
+
+            // A failed fetch isn't cached, so the next run tries again
+            _ => match fetch_timeline(github, owner, repo, id as u64).await {
+                Some(issues) => {
+                    cache.insert_timeline(key, issues.clone());
+                    issues
+                }
+                None => continue,
+            },
+        };
+
+        for issue in issues {
+            merge(&mut commit.linked_issues, issue);
+        }
+    }
+
+    Ok(())
+}
+
+/// The issues from the timeline of a PR, or `None` with the failure logged
+async fn fetch_timeline(
+    github: &dyn GitHub,
+    owner: &str,
+    repo: &str,
+    number: u64,
+) -> Option<Vec<LinkedIssue>> {
+    match github.timeline(owner, repo, number).await {
+        Ok(events) => Some(parse_timeline(&events)),
+        Err(err) => {
+            eprintln!("Failed to get the timeline of {owner}/{repo}#{number}: {err}");
+            None
+        }
+    }
+}
+
+/// Add an issue to a list, upgrading a reference to a closing one if it's already there
+pub fn merge(issues: &mut Vec<LinkedIssue>, issue: LinkedIssue) {
+    let existing = issues
+        .iter_mut()
+        .find(|other| other.number == issue.number && other.repo.eq_ignore_ascii_case(&issue.repo));
+
+    match existing {
+        Some(existing) => existing.closes |= issue.closes,
+        None => issues.push(issue),
+    }
+}
+
+/// Pull the issues out of the `cross-referenced` events of a timeline
+///
+/// Other PRs show up in there too, but they have a `pull_request` key so we can skip them
+fn parse_timeline(events: &Value) -> Vec<LinkedIssue> {
+    let mut issues = vec![];
+
+    for event in events.as_array().into_iter().flatten() {
+        if event["event"] != "cross-referenced" {
+            continue;
+        }
+
+        let source = &event["source"]["issue"];
+        if !source["pull_request"].is_null() {
+            continue;
+        }
+
+        let (Some(number), Some(repo)) = (
+            source["number"].as_u64(),
+            source["repository"]["full_name"].as_str(),
+        ) else {
+            continue;
+        };
+
+        merge(
+            &mut issues,
+            LinkedIssue {
+                repo: repo.to_string(),
+                number: number as usize,
+                closes: false,
+            },
+        );
+    }
+
+    issues
+}
+
+#[test]
+fn links_issues_from_text_and_timeline() {
+    let issues = from_text(
+        "DioxusLabs/dioxus",
+        "Fix the router on windows (#2150)\n\nFixes #2001, see #2001 and DioxusLabs/blitz#33\n\nReverts #1200",
+    );
+
+    // The mentions could be PRs just as well, so only the closed issue counts
+    assert_eq!(
+        issues,
+        vec![LinkedIssue {
+            repo: "DioxusLabs/dioxus".to_string(),
+            number: 2001,
+            closes: true,
+        }]
+    );
+
+    let timeline = serde_json::json!([
+        { "event": "labeled", "label": { "name": "bug" } },
+        {
+            "event": "cross-referenced",
+            "source": { "issue": { "number": 2010, "repository": { "full_name": "DioxusLabs/dioxus" } } }
+        },
+        {
+            "event": "cross-referenced",
+            "source": {
+                "issue": {
+                    "number": 2011,
+                    "pull_request": { "url": "https://api.github.com/repos/DioxusLabs/dioxus/pulls/2011" },
+                    "repository": { "full_name": "DioxusLabs/dioxus" }
+                }
+            }
+        }
+    ]);
+
+    assert_eq!(
+        parse_timeline(&timeline),
+        vec![LinkedIssue {
+            repo: "DioxusLabs/dioxus".to_string(),
+            number: 2010,
+            closes: false,
+        }]
+    );
+}
+
+#[tokio::test]
+async fn only_refetches_the_timeline_of_unreleased_prs() {
+    let config = DoxieConfig::default();
+    let mut cache = CommitCache::in_memory();
+
+    // A run before this one found an issue in the timeline of #103, which the fixture doesn't have
+    let linked = LinkedIssue {
+        repo: config.main_repo.clone(),
+        number: 90,
+        closes: false,
+    };
+    cache.insert_timeline(format!("{}#103", config.main_repo), vec![linked.clone()]);
+
+    let commit: PrCommit = serde_json::from_value(serde_json::json!({
+        "summary": "Fix a panic in the web renderer (#103)",
+        "id": 103,
+        "changed_packages": ["dioxus-web"],
+        "commit_hash": "c08f514e6b1fd2d1bb1a7bdc5b5b1b4e7f1c2a3d",
+        "head_index": 0,
+    }))
+    .unwrap();
+
+    let mut released = [commit.clone()];
+    add_timeline(
+        &crate::fixtures::github(),
+        &config,
+        &mut released,
+        &mut cache,
+        true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(released[0].linked_issues, [linked]);
+
+    // Unreleased PRs always ask again, and the answer replaces what was cached
+    let mut unreleased = [commit];
+    add_timeline(
+        &crate::fixtures::github(),
+        &config,
+        &mut unreleased,
+        &mut cache,
+        false,
+    )
+    .await
+    .unwrap();
+    assert!(unreleased[0].linked_issues.is_empty());
+    assert_eq!(
+        cache.timeline(&format!("{}#103", config.main_repo)),
+        Some(&vec![])
+    );
+}
@@ -3,23 +3,200 @@
 use std::{
     collections::{HashMap, HashSet},
     future::Future,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 
+use clap::Parser;
 use doxie_types::*;
 use git2::{Commit, Oid, Repository, Revwalk};
-use tokio::process::Command;
-
+use serde::Serialize;
+
+mod backport_candidates;
+mod backports;
+mod benchmarks;
+mod churn;
+mod cli;
+mod comment;
+mod commit_cache;
+mod crates_index;
+mod error;
+#[cfg(test)]
+mod fixtures;
+mod github;
+mod linked_issues;
+mod perf_compare;
+mod pr_refs;
+mod rate_limit;
+mod regressions;
+mod release_queue;
+mod releases;
+mod response_cache;
+mod semver_checks;
+mod sizes;
+mod wasm_symbols;
 mod workflow;
+mod workspace;
+
+use backports::BackportIndex;
+use cli::{Cli, Command as CliCommand};
+use commit_cache::{CachedCommit, CommitCache};
+use error::{Diagnostics, DoxieError, Result};
+use github::GitHub;
+use releases::{ReleaseTag, TagIndex};
+use workspace::{Workspace, WorkspaceCache};
 
 const OUTPUT_DIR: &str = "data";
 
 #[tokio::main]
-async fn main() {
-    let root = PathBuf::from("/Users/jonkelley/Development/dioxus");
+async fn main() -> ExitCode {
+    match run(Cli::parse()).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+    let config = cli.command.shared().load_config()?;
+    let github = cli.command.shared().github(&config)?;
+    let github = github.as_ref();
+
+    let result = match cli.command {
+        CliCommand::Commits {
+            shared,
+            semver_checks,
+        } => {
+            let repo = Repository::open(&shared.repo_path)?;
+            changed_crates_on_repo(
+                github,
+                &repo,
+                &shared.repo_path,
+                &config,
+                &shared.output_dir,
+                semver_checks,
+                shared.github_token.is_some(),
+                shared.diagnostics(),
+            )
+            .await
+        }
+        CliCommand::OpenPrs {
+            shared,
+            semver_checks,
+        } => {
+            all_open_prs(
+                github,
+                &config,
+                &shared.repo_path,
+                &shared.output_dir,
+                semver_checks,
+                shared.github_token.is_some(),
+                shared.diagnostics(),
+            )
+            .await
+        }
+        CliCommand::Stats {
+            shared,
+            baseline,
+            branch,
+        } => {
+            collect_stats(
+                &shared.repo_path,
+                &config,
+                &shared.output_dir,
+                baseline.as_deref(),
+                branch.as_deref(),
+                shared.diagnostics(),
+            )
+            .await
+        }
+        CliCommand::Regressions(args) => {
+            let history = regressions::load_history(&args.output_dir)?;
+            let report = regressions::detect(&history, &config.benchmarks, &config.main_repo);
+
+            for step in report.change_points.iter() {
+                println!(
+                    "{:?} in {} on {} at {}: {:+.1}%",
+                    step.verdict,
+                    step.benchmark,
+                    step.branch,
+                    step.pr_name,
+                    (step.ratio - 1.0) * 100.0
+                );
+            }
+
+            write_blob(&args.output_dir, "regressions.json", &report)
+        }
+        CliCommand::Comment { shared, pr } => {
+            write_status_comment(
+                github,
+                &shared.repo_path,
+                &config,
+                &shared.output_dir,
+                pr,
+                shared.github_token.is_some(),
+            )
+            .await
+        }
+        CliCommand::StatusBlob(args) => write_status_blob(github, &config, &args.output_dir).await,
+        CliCommand::Backports {
+            shared,
+            semver_checks,
+        } => {
+            let repo = Repository::open(&shared.repo_path)?;
+            backport_candidates::collect_backport_candidates(
+                github,
+                &repo,
+                &shared.repo_path,
+                &config,
+                &shared.output_dir,
+                semver_checks,
+                shared.diagnostics(),
+            )
+            .await
+        }
+        CliCommand::ReleaseQueue { shared, index_path } => {
+            let repo = Repository::open(&shared.repo_path)?;
+            release_queue::collect_release_queue(
+                &repo,
+                &config,
+                &index_path,
+                &shared.output_dir,
+                shared.diagnostics(),
+            )
+        }
+        CliCommand::Artifact(args) => {
+            save_stats_as_artifact(
+                github,
+                &args.repo_path,
+                &config,
+                &args.output_dir,
+                args.github_token.is_some(),
+                args.keep_going,
+            )
+            .await
+        }
+    };
+
+    // Save the response cache even if the run failed halfway, so the retry doesn't start from scratch
+    let finished = github.finish();
+    result.and(finished)
+}
+
+/// Write one of the json blobs to the output dir, creating the dir if it's missing
+fn write_blob(out_dir: &Path, name: &str, value: &impl Serialize) -> Result<()> {
+    std::fs::create_dir_all(out_dir).map_err(DoxieError::io("create", out_dir))?;
 
-    // For now, just write to the stats cache as the default
-    save_stats_as_artifact(root).await;
+    let blob = if cfg!(debug_assertions) {
+        serde_json::to_string_pretty(value)?
+    } else {
+        serde_json::to_string(value)?
+    };
+
+    let path = out_dir.join(name);
+    std::fs::write(&path, blob).map_err(DoxieError::io("write", &path))
 }
 
 async fn bot_loop() {}
@@ -39,7 +216,65 @@ async fn bot_loop() {}
 ///
 /// This is structured in such a way that we completely overwrite a comment, so it needs to be
 /// deterministic with maybe an "updated time" to show that it's been updated.
-async fn write_status_comment() {}
+async fn write_status_comment(
+    github: &dyn GitHub,
+    repo_path: &Path,
+    config: &DoxieConfig,
+    out_dir: &Path,
+    pr: u64,
+    with_timeline: bool,
+) -> Result<()> {
+    github.start_collector("comment");
+    let (owner, name) = config.main_owner_and_repo()?;
+
+    let pull = github.pull(owner, name, pr).await?;
+
+    let findings = semver_checks::check_pr(repo_path, &config.main_repo, pr, &pull.base.sha).await;
+
+    let text = format!(
+        "{}\n\n{}",
+        pull.title.as_deref().unwrap_or_default(),
+        pull.body.as_deref().unwrap_or_default()
+    );
+    let mut issues = linked_issues::from_text(&config.main_repo, &text);
+    if with_timeline {
+        for issue in linked_issues::from_timeline(github, owner, name, pr).await {
+            linked_issues::merge(&mut issues, issue);
+        }
+    }
+
+    let size_diff = size_diff(out_dir, &pull.base.sha, &pull.head.sha)?;
+
+    let body = comment::render(
+        &pull.head.sha,
+        findings.as_deref(),
+        &issues,
+        size_diff.as_deref(),
+    );
+
+    comment::upsert(github, owner, name, pr, &body).await
+}
+
+/// The wasm size table for a PR, if `doxie stats` ran on both its base and its head
+fn size_diff(out_dir: &Path, base: &str, head: &str) -> Result<Option<String>> {
+    let history = regressions::load_history(out_dir)?;
+
+    // The stats of the last run only make it into the history if it was on a branch
+    let latest = std::fs::read_to_string(out_dir.join("stats.json"))
+        .ok()
+        .and_then(|raw| serde_json::from_str::<CommitStats>(&raw).ok());
+    let find = |sha: &str| {
+        latest
+            .as_ref()
+            .filter(|stats| stats.sha == sha)
+            .or_else(|| history.find(sha))
+    };
+
+    Ok(match (find(base), find(head)) {
+        (Some(base), Some(head)) => sizes::render_diff(base, head),
+        _ => None,
+    })
+}
 
 /// A single page that shows all the changes for the current tip of main
 ///
@@ -56,119 +291,284 @@ async fn write_status_comment() {}
 /// This includes:
 /// - Performance of a particular set of benchmarks in a table format
 /// - Binary size of a particular crate compiled to wasm with different lines for opt levels, compression, etc
-async fn write_status_blob() {}
+///
+/// For now the blob has the open PRs of the main repo and whatever `doxie stats` has recorded so far, so
+/// run it after the stats.
+async fn write_status_blob(
+    github: &dyn GitHub,
+    config: &DoxieConfig,
+    out_dir: &Path,
+) -> Result<()> {
+    github.start_collector("status");
+    let (owner, name) = config.main_owner_and_repo()?;
+
+    let open_prs = github.open_prs(owner, name).await?;
+    let history = regressions::load_history(out_dir)?;
+
+    let status = RepoStatus {
+        repo: config.main_repo.clone(),
+        open_prs: open_prs.total_count,
+        regressions: regressions::detect(&history, &config.benchmarks, &config.main_repo),
+        latest_stats: history
+            .stats
+            .into_iter()
+            .filter_map(|(branch, mut history)| Some((branch, history.stats.pop()?)))
+            .collect(),
+    };
+
+    write_blob(out_dir, "status.json", &status)
+}
 
 /// Run all the benchmarks, tests, etc and save their output as a single json blob
 ///
-/// This should be executing various `cargo make xyz` things and capturing their outputs.
-async fn collect_stats() {}
+/// The stats are for whatever is checked out at `repo_path` and end up in `stats.json`, as well as in the
+/// branch's history. With a baseline, the benchmarks of both commits run in worktrees instead
+/// and the comparison is saved too.
+async fn collect_stats(
+    repo_path: &Path,
+    config: &DoxieConfig,
+    out_dir: &Path,
+    baseline: Option<&str>,
+    branch: Option<&str>,
+    mut diagnostics: Diagnostics,
+) -> Result<()> {
+    let repo = Repository::open(repo_path)?;
+    let head = repo.head()?.peel_to_commit()?;
+
+    let perf = match baseline {
+        None => benchmarks::run(repo_path, &config.benchmarks, &mut diagnostics).await?,
+        Some(baseline) => {
+            let baseline = repo.revparse_single(baseline)?.peel_to_commit()?.id();
+            let (comparison, perf) = perf_compare::compare(
+                repo_path,
+                &baseline.to_string(),
+                &head.id().to_string(),
+                &config.benchmarks,
+                &mut diagnostics,
+            )
+            .await?;
+
+            for change in comparison.regressions().chain(comparison.improvements()) {
+                println!(
+                    "{:?} in {}: {:+.1}% (p = {:.3})",
+                    change.verdict,
+                    change.name,
+                    (change.ratio - 1.0) * 100.0,
+                    change.p_value
+                );
+            }
 
-/// Collect all the open PRs across the various repos
-async fn all_open_prs() {
-    let repos = [
-        "dioxuslabs/dioxus",
-        "dioxuslabs/dioxus-template",
-        "dioxuslabs/docsite",
-        "dioxuslabs/blitz",
-        "dioxuslabs/components",
-        "dioxuslabs/sdk",
-        "dioxuslabs/collect-assets",
-        "dioxuslabs/include_mdbook",
-        "dioxuslabs/example-projects",
-        "dioxuslabs/awesome-dioxus",
-        "jkelleyrtp/stylo-dioxus",
-    ];
+            write_blob(out_dir, "perf_comparison.json", &comparison)?;
+            perf
+        }
+    };
+
+    let compile_size = sizes::collect(repo_path, &config.sizes, &mut diagnostics).await;
+
+    let stats = CommitStats {
+        pr_name: String::from_utf8_lossy(head.summary_bytes().unwrap_or_default()).into_owned(),
+        sha: head.id().to_string(),
+        commit_time: head.time().seconds(),
+        perf,
+        compile_size,
+        diagnostics: diagnostics.into_entries(),
+        ..Default::default()
+    };
+
+    write_blob(out_dir, "stats.json", &stats)?;
 
+    let branch = branch.map(str::to_string).or_else(|| {
+        let head = repo.head().ok().filter(|head| head.is_branch())?;
+        head.shorthand().map(str::to_string)
+    });
+    let Some(branch) = branch else {
+        println!("HEAD is detached, so the stats aren't added to the history of a branch");
+        return Ok(());
+    };
+
+    let mut history = regressions::load_history(out_dir)?;
+    history.last_updated = head.time().seconds().to_string();
+    history.record(&branch, stats, config.benchmarks.history_length);
+    write_blob(out_dir, regressions::HISTORY_FILE, &history)
+}
+
+/// Collect all the open PRs across the various repos
+///
+/// We only have a checkout of the main repo, so that's the only one the semver checks run for
+async fn all_open_prs(
+    github: &dyn GitHub,
+    config: &DoxieConfig,
+    repo_path: &Path,
+    out_dir: &Path,
+    run_semver_checks: bool,
+    with_timeline: bool,
+    mut diagnostics: Diagnostics,
+) -> Result<()> {
+    github.start_collector("open_prs");
     let mut saved_repos = HashMap::new();
-    let octocrab = octocrab::instance();
 
-    for repo in repos {
-        let (owner, repo) = repo.split_once('/').unwrap();
+    for full_name in config.repos.iter() {
+        let Some((owner, repo)) = full_name.split_once('/') else {
+            let err = DoxieError::InvalidRepo(full_name.clone());
+            diagnostics.recover(format!("repo {full_name}"), Err::<(), _>(err))?;
+            continue;
+        };
 
-        let prs = octocrab
-            .pulls(owner, repo)
-            .list()
-            .per_page(100)
-            .state(octocrab::params::State::Open)
-            .send()
-            .await;
+        let prs = github.open_prs(owner, repo).await;
+
+        if let Some(listing) = diagnostics.recover(format!("repo {full_name}"), prs)? {
+            let prs = listing.items;
+            let mut semver_findings = HashMap::new();
+            if run_semver_checks && *full_name == config.main_repo {
+                for pr in prs.iter() {
+                    let findings =
+                        semver_checks::check_pr(repo_path, full_name, pr.number, &pr.base.sha)
+                            .await;
+
+                    if let Some(findings) = findings {
+                        semver_findings.insert(pr.number, findings);
+                    }
+                }
+            }
+
+            let mut linked = HashMap::new();
+            for pr in prs.iter() {
+                let text = format!(
+                    "{}\n\n{}",
+                    pr.title.as_deref().unwrap_or_default(),
+                    pr.body.as_deref().unwrap_or_default()
+                );
+                let mut issues = linked_issues::from_text(full_name, &text);
+
+                if with_timeline {
+                    for issue in linked_issues::from_timeline(github, owner, repo, pr.number).await
+                    {
+                        linked_issues::merge(&mut issues, issue);
+                    }
+                }
+
+                linked.insert(pr.number, issues);
+            }
 
-        if let Ok(prs) = prs {
             saved_repos.insert(
                 repo.to_string(),
                 OpenPrs {
                     repo: repo.to_string(),
-                    prs: prs.into_iter().collect(),
+                    prs,
+                    total_count: listing.total_count,
+                    semver_findings,
+                    linked_issues: linked,
                 },
             );
-        } else {
-            eprintln!("Failed to get PRs for {}", repo);
         }
     }
 
-    let blob = if cfg!(debug_assertions) {
-        serde_json::to_string_pretty(&OpenPrMap { prs: saved_repos }).unwrap()
-    } else {
-        serde_json::to_string(&OpenPrMap { prs: saved_repos }).unwrap()
+    let open_prs = OpenPrMap {
+        prs: saved_repos,
+        diagnostics: diagnostics.into_entries(),
     };
 
-    let out_dir = OUTPUT_DIR.parse::<PathBuf>().unwrap();
-    std::fs::write(out_dir.join("open_prs.json"), blob).unwrap();
+    write_blob(out_dir, "open_prs.json", &open_prs)
 }
 
 #[tokio::test]
 async fn collect_open_prs() {
-    all_open_prs().await;
-}
+    let github = fixtures::github();
+    let out_dir = tempfile::tempdir().unwrap();
+    let config = DoxieConfig {
+        repos: vec![
+            "dioxuslabs/dioxus".to_string(),
+            "dioxuslabs/docsite".to_string(),
+            "dioxuslabs/missing".to_string(),
+        ],
+        ..Default::default()
+    };
 
-/// Build the wasm examples and optimize them, and then save the
-async fn collect_size(name: String) -> CompileSizeStats {
-    // Build without optimizations
-    let cmd = Command::new("dx").args(["build", "--release", "--no-opt"]);
-    let raw_debug_wasm_size = 0;
+    all_open_prs(
+        &github,
+        &config,
+        ".".as_ref(),
+        out_dir.path(),
+        false,
+        true,
+        Diagnostics::new(true),
+    )
+    .await
+    .unwrap();
 
-    // Build with optimizations
-    let cmd = Command::new("dx").args(["build", "--release"]);
-    let optimized_wasm_size = 0;
+    let blob = fixtures::read_blob(out_dir.path().join("open_prs.json"));
+    let open_prs: OpenPrMap = serde_json::from_value(blob).unwrap();
 
-    CompileSizeStats {
-        name,
-        raw_debug_wasm_size,
-        optimized_wasm_size,
-    }
+    assert_eq!(open_prs.prs["dioxus"].prs.len(), 15);
+    assert_eq!(open_prs.prs["dioxus"].total_count, 15);
+    assert!(open_prs.prs["dioxus"].is_complete());
+    assert_eq!(open_prs.prs["docsite"].prs.len(), 5);
+
+    let issues = &open_prs.prs["dioxus"].linked_issues[&2302];
+    let numbers: Vec<_> = issues.iter().map(|issue| issue.number).collect();
+    assert_eq!(numbers, [2295, 2294]);
+
+    // The repo that can't be listed is recorded instead of ending the run
+    assert_eq!(open_prs.prs.len(), 2);
+    assert_eq!(open_prs.diagnostics[0].scope, "repo dioxuslabs/missing");
 }
 
 #[tokio::test]
-async fn changed_prs__() {
-    let octocrab = octocrab::instance();
+async fn writes_one_status_comment() {
+    let github = fixtures::github();
+    let config = DoxieConfig::default();
+
+    // Not a checkout, so the semver checks can't run
+    let repo_path = tempfile::tempdir().unwrap();
+
+    let out_dir = tempfile::tempdir().unwrap();
+
+    for _ in 0..2 {
+        write_status_comment(
+            &github,
+            repo_path.path(),
+            &config,
+            out_dir.path(),
+            2365,
+            true,
+        )
+        .await
+        .unwrap();
+    }
 
-    changed_on_prs(&octocrab).await;
+    let comments = github.comments("dioxuslabs", "dioxus", 2365).await.unwrap();
+    assert_eq!(comments.len(), 1);
+    assert!(comments[0].body.starts_with(comment::MARKER));
+    assert!(comments[0].body.contains("- Closes dioxuslabs/dioxus#1335"));
+    assert!(comments[0].body.contains("couldn't run"));
 }
 
-async fn changed_on_prs(api: &octocrab::Octocrab) {
-    use octocrab::params::State as PrState;
-
-    // Quickly check if this PR has any artifacts with cached data.
-    // This should just let us skip any work we need to do. Eventually all the PRs will have this work
-    // done for them automatically in the workflow.
-    let prs = api
-        .pulls("dioxuslabs", "dioxus")
-        .list()
-        .state(PrState::Open)
-        .per_page(100)
-        .send()
+#[tokio::test]
+async fn writes_the_status_blob() {
+    let out_dir = tempfile::tempdir().unwrap();
+    let config = DoxieConfig::default();
+
+    let mut history = Fullstats::default();
+    for (sha, commit_time) in [("b", 2), ("a", 1)] {
+        let stats = CommitStats {
+            sha: sha.to_string(),
+            commit_time,
+            ..Default::default()
+        };
+        history.record("main", stats, 10);
+    }
+    write_blob(out_dir.path(), regressions::HISTORY_FILE, &history).unwrap();
+
+    write_status_blob(&fixtures::github(), &config, out_dir.path())
         .await
         .unwrap();
 
-    for pr in prs {
-        println!(
-            "PR [{login}] {title} - {id}\n{body}\n",
-            id = pr.id,
-            body = pr.body.as_deref().unwrap_or_default(),
-            title = pr.title.as_deref().unwrap_or_default(),
-            login = &pr.user.as_ref().unwrap().login
-        );
-    }
+    let status: RepoStatus =
+        serde_json::from_value(fixtures::read_blob(out_dir.path().join("status.json"))).unwrap();
+    assert_eq!(status.repo, config.main_repo);
+    assert_eq!(status.open_prs, 15);
+    assert_eq!(status.latest_stats["main"].sha, "b");
+    assert!(status.regressions.change_points.is_empty());
 }
 
 /// Use the github API to figure out what packages have been changed by a given PR
@@ -180,7 +580,7 @@ async fn changed_on_prs(api: &octocrab::Octocrab) {
 ///
 /// Alternatively, we could run the diffing here on the PR itself and save it as an artifact which this
 /// bot reads.
-async fn changed_on_pr(api: &octocrab::Octocrab, pr_id: usize) {}
+async fn changed_on_pr(api: &dyn GitHub, pr_id: usize) {}
 
 /// Whenever a PR is merged, we kick off the workflow to build the stats for the new main branch and then
 /// save that as a single unified blob to the repo.
@@ -190,34 +590,180 @@ async fn changed_on_pr(api: &octocrab::Octocrab, pr_id: usize) {}
 ///
 /// We should also try to implement some sort of caching/versioning CDN-like mechanism so we don't
 /// run into issues. GH gives us 12.5k req/hr which could add up in DDOS scenario
-async fn save_stats_as_artifact(path: PathBuf) {
-    // For now, collect all the PRs just for 0.4 and 0.5
-    let repo = Repository::open(path).unwrap();
-    changed_crates_on_repo(&repo);
+async fn save_stats_as_artifact(
+    github: &dyn GitHub,
+    path: &Path,
+    config: &DoxieConfig,
+    out_dir: &Path,
+    with_timeline: bool,
+    keep_going: bool,
+) -> Result<()> {
+    // Collect all the PRs for the release lines in the config
+    let repo = Repository::open(path)?;
+    let diagnostics = Diagnostics::new(keep_going);
+    changed_crates_on_repo(
+        github,
+        &repo,
+        path,
+        config,
+        out_dir,
+        false,
+        with_timeline,
+        diagnostics,
+    )
+    .await?;
+
+    // Queue up anything that should make it into the next patch release
+    // The semver checks are what keeps unlabeled breaking changes out of the queue, so they always run
+    let diagnostics = Diagnostics::new(keep_going);
+    backport_candidates::collect_backport_candidates(
+        github,
+        &repo,
+        path,
+        config,
+        out_dir,
+        true,
+        diagnostics,
+    )
+    .await?;
 
     // And then list open PRs
-    all_open_prs().await;
+    let diagnostics = Diagnostics::new(keep_going);
+    all_open_prs(
+        github,
+        config,
+        path,
+        out_dir,
+        false,
+        with_timeline,
+        diagnostics,
+    )
+    .await
 }
 
-fn changed_crates_on_repo(repo: &Repository) {
-    let changed = ChangedVersions {
-        version: vec![
-            (4, collect_prs_for_minor_version(repo, 4)),
-            (5, collect_prs_for_minor_version(repo, 5)),
-        ]
-        .into_iter()
-        .collect(),
-    };
+#[allow(clippy::too_many_arguments)]
+async fn changed_crates_on_repo(
+    github: &dyn GitHub,
+    repo: &Repository,
+    repo_path: &Path,
+    config: &DoxieConfig,
+    out_dir: &Path,
+    run_semver_checks: bool,
+    with_timeline: bool,
+    mut diagnostics: Diagnostics,
+) -> Result<()> {
+    github.start_collector("commits");
+
+    // Only the commits that landed since the last run need to be diffed
+    let mut cache = CommitCache::load(out_dir, config);
+
+    let tag_index = TagIndex::new(repo, &config.releases, &mut cache)?;
+    let backports = BackportIndex::new(repo, config, tag_index.lines())?;
+
+    // Only one tag per version makes it into the report, so say which ones got passed over
+    for (version, names) in tag_index.ambiguous() {
+        for name in &names[1..] {
+            diagnostics.record(
+                format!("tag {name}"),
+                DoxieError::AmbiguousTag {
+                    version: version.to_string(),
+                    picked: names[0].clone(),
+                },
+            );
+        }
+    }
 
-    let blob = if cfg!(debug_assertions) {
-        serde_json::to_string_pretty(&changed).unwrap()
-    } else {
-        serde_json::to_string(&changed).unwrap()
+    let head_tree = repo.head()?.peel_to_tree()?;
+    let crate_paths = Workspace::from_tree(repo, &head_tree)
+        .map(|workspace| {
+            workspace
+                .crates
+                .into_iter()
+                .map(|krate| (krate.name, krate.path.to_string_lossy().to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut changed = ChangedVersions {
+        version: HashMap::new(),
+        crate_paths,
+        diagnostics: vec![],
     };
 
-    let out_dir = OUTPUT_DIR.parse::<PathBuf>().unwrap();
+    for (&(major, minor), tags) in tag_index.lines().iter() {
+        if !releases::is_reported(&config.releases, (major, minor)) {
+            continue;
+        }
+
+        // Once a newer line exists, this line stops collecting the unreleased commits on HEAD
+        // This includes prereleases, so a `v0.6.0-alpha.0` ends the 0.5 line
+        let has_next_line = tag_index
+            .lines()
+            .range((major, minor + 1)..)
+            .next()
+            .is_some();
+
+        // The first tag of this line picks up from the last release before it
+        let previous = tag_index.previous_release(&tags[0].version);
+
+        // A line that fails to collect is left out of the report
+        let minor_version = collect_prs_for_minor_version(
+            repo,
+            config,
+            &backports,
+            &mut cache,
+            &mut diagnostics,
+            previous,
+            tags,
+            has_next_line,
+        );
+
+        let Some(mut minor_version) =
+            diagnostics.recover(format!("release line {major}.{minor}"), minor_version)?
+        else {
+            continue;
+        };
+
+        // Catch anything that would break the next patch release before it gets published
+        if run_semver_checks {
+            let baseline = &tags.last().unwrap().name;
+            for patch in minor_version.patch_versions.iter_mut() {
+                if !patch.published {
+                    semver_checks::check_unreleased(
+                        repo_path,
+                        baseline,
+                        &mut patch.commits,
+                        &mut diagnostics,
+                    )
+                    .await;
+                }
+            }
+        }
+
+        // Issues that point at a PR without the PR pointing back only show up in the timeline
+        if with_timeline {
+            for patch in minor_version.patch_versions.iter_mut() {
+                linked_issues::add_timeline(
+                    github,
+                    config,
+                    &mut patch.commits,
+                    &mut cache,
+                    patch.published,
+                )
+                .await?;
+            }
+        }
+
+        changed
+            .version
+            .insert(format!("{major}.{minor}"), minor_version);
+    }
+
+    cache.save()?;
 
-    std::fs::write(out_dir.join("commits.json"), blob).unwrap();
+    changed.diagnostics = diagnostics.into_entries();
+
+    write_blob(out_dir, "commits.json", &changed)
 }
 
 /// collect the prs from the main repo
@@ -231,50 +777,41 @@ fn changed_crates_on_repo(repo: &Repository) {
 /// - should this pr be backported?
 async fn collect_prs() {}
 
-fn sha_from_tag(repo: &Repository, target_tag: &str) -> Option<Oid> {
-    let mut id = None;
-
-    _ = repo.tag_foreach(|this_id, bytes| {
-        let name = std::str::from_utf8(bytes).unwrap();
-
-        if name.ends_with(target_tag) {
-            let obj = repo.revparse_single(name).unwrap();
-
-            if let Some(tag) = obj.as_tag() {
-                id = Some(tag.target_id());
-            }
-
-            if let Some(commit) = obj.as_commit() {
-                id = Some(commit.id());
-            }
-        }
+/// What we learn about a commit while diffing it
+#[derive(Debug)]
+struct Pr {
+    changed_files: HashSet<PathBuf>,
 
-        true
-    });
+    /// The names of the workspace crates that own the changed files
+    ///
+    /// `None` if the commit has no cargo workspace we could read, in which case we fall back to the
+    /// folder names under the package roots
+    workspace_crates: Option<HashSet<String>>,
 
-    id
-}
+    /// Whether the root `Cargo.toml` or `Cargo.lock` changed
+    affects_workspace: bool,
 
-#[derive(Debug)]
-struct Pr<'a> {
-    commit: Commit<'a>,
+    /// The patch-id of the diff, used to match cherry-picks on the stable branches back to this commit
+    patch_id: Option<Oid>,
 
-    /// The `#id` of the PR - so you can go to github.com/dioxuslabs/dioxus/pull/id
-    id: Option<usize>,
+    /// The lines changed, leaving out the excluded files
+    churn: ChurnStats,
 
-    changed_files: HashSet<PathBuf>,
-
-    idx: usize,
+    /// The lines changed in each workspace crate
+    churn_by_crate: HashMap<String, ChurnStats>,
 }
 
-impl Pr<'_> {
-    //
-    pub fn changed_crates(&self) -> Vec<PathBuf> {
+impl Pr {
+    /// The changed files that live under one of the package roots, relative to that root
+    pub fn changed_crates(&self, package_roots: &[String]) -> Vec<PathBuf> {
         let mut packages = vec![];
 
         for file in self.changed_files.iter() {
-            if file.starts_with("packages") {
-                packages.push(file.clone());
+            for root in package_roots {
+                if let Ok(relative) = file.strip_prefix(root) {
+                    packages.push(relative.to_path_buf());
+                    break;
+                }
             }
         }
 
@@ -282,18 +819,20 @@ impl Pr<'_> {
     }
 
     // get the name of the package that changed
-    // just parse the first folder after "packages/"
-    pub fn changed_packages(&self) -> Vec<String> {
+    // if there's no workspace, just parse the first folder after the package root (ie "packages/")
+    pub fn changed_packages(&self, package_roots: &[String]) -> Vec<String> {
+        if let Some(crates) = &self.workspace_crates {
+            let mut out: Vec<String> = crates.iter().cloned().collect();
+            out.sort();
+            return out;
+        }
+
         let mut out: HashSet<String> = self
-            .changed_crates()
+            .changed_crates(package_roots)
             .iter()
-            .map(|path| {
-                path.iter()
-                    .skip(1)
-                    .take(1)
-                    .map(|os_str| os_str.to_str().unwrap().to_string())
-                    .collect()
-            })
+            .filter(|path| path.components().count() > 1)
+            .filter_map(|path| path.iter().next())
+            .map(|os_str| os_str.to_string_lossy().to_string())
             .collect();
 
         let mut out: Vec<String> = out.into_iter().collect();
@@ -304,187 +843,509 @@ impl Pr<'_> {
     }
 }
 
-/// Manually look at the shas between versions
-async fn collect_changed_crates(repo: PathBuf) {
-    // Run through each tag starting at 0.5.0 and increment the patch version
-    // We're trying to get a sense of which crates changed between versions
-    // We want to link these to PRs by checking the git log for "merged" PR logs
-    // git log --first-parent/ / to get shas for every PR in the past
-
-    let repo = Repository::open(repo).unwrap();
-    let start_id = sha_from_tag(&repo, "v0.5.0").unwrap();
-    let end_id = sha_from_tag(&repo, "v0.5.1").unwrap();
-
-    collect_pr_between(&repo, end_id, start_id);
-}
-
-/// Walk all the tags between the two minor versions and collect the PRs for each release
+/// Walk all the tags of a release line and collect the PRs for each release
+///
+/// so if we're going from 0.5.0 to 0.5.1, the 0.5.1 entry has all the PRs between those two tags
 ///
-/// so if we're going from 0.5.0 to 0.5.1, we'll collect all the PRs between those two tags
+/// Prereleases get their own entry, so 0.6.0-alpha.1 lists the PRs since 0.6.0-alpha.0 and 0.6.0
+/// lists the PRs since the last prerelease.
 ///
-/// minor_version would be 5 in this case
-/// Does not cover prereleases - only releases in the form
-fn collect_prs_for_minor_version(repo: &Repository, minor_version: usize) -> MinorVersionChanged {
-    // we're going to march forward version by version until the tag doesn't show up and then give up
-    // Kinda dumb but it keeps the structure simple enough
-    let mut patch_version = 0;
+/// The tags are expected to be sorted, which is what `TagIndex::releases_in_line` hands back.
+/// `previous` is the last release before this line, which the first tag of this line starts from
+#[allow(clippy::too_many_arguments)]
+fn collect_prs_for_minor_version(
+    repo: &Repository,
+    config: &DoxieConfig,
+    backports: &BackportIndex,
+    cache: &mut CommitCache,
+    diagnostics: &mut Diagnostics,
+    previous: Option<&ReleaseTag>,
+    tags: &[ReleaseTag],
+    has_next_line: bool,
+) -> Result<MinorVersionChanged> {
     let mut patch_versions = vec![];
+    let mut start = previous;
+
+    for tag in tags {
+        // The very first release of the repo has nothing to compare against
+        if let Some(start) = start {
+            // The previous tag might live on a stable branch, in which case the walk stops where the
+            // two diverged
+            let commits = collect_pr_between(
+                repo,
+                config,
+                backports,
+                cache,
+                diagnostics,
+                tag.id,
+                start.id,
+            )?;
+            patch_versions.push(patch_version_changed(
+                repo,
+                tag.id,
+                commits,
+                tag.version.patch as usize,
+                tag.pre(),
+                true,
+            ));
+        }
 
-    loop {
-        let start_tag = format!("v0.{}.{}", minor_version, patch_version);
-        let end_tag = format!("v0.{}.{}", minor_version, patch_version + 1);
-
-        let start_id = sha_from_tag(repo, &start_tag).unwrap();
-        let end_id = sha_from_tag(repo, &end_tag);
-
-        // If there's no end_id, that was the last tag for this minor version
-        // If there's a v0.6.0, then we're done
-        // If there's not v0.6.0, then we should accumulate the remaining changes as the "next" version
-        if end_id.is_none() {
-            println!("No end tag for {}", end_tag);
+        start = Some(tag);
+    }
 
-            // todo: Also handle prereleases in the form of alpha-0
-            let next_minor = format!("v0.{}.0", minor_version + 1);
-            if sha_from_tag(repo, &next_minor).is_some() {
-                break;
-            }
+    // The last tag of the line has no end tag
+    // If there's a newer line (ie v0.6.0 or v0.6.0-alpha.0 for v0.5), then we're done
+    // If there's not, then we should accumulate the remaining changes as the "next" version
+    let last = tags.last().unwrap();
+    if !has_next_line {
+        println!(
+            "No next minor version found after {}, attempting to collect from HEAD",
+            last.name
+        );
 
-            println!("No next minor version found, attempting to collect from HEAD");
-
-            // If there's no next minor version, then the remaining tags are for *this* version
-            // The idea being that once breaking changes exist in the form of a new minor version, we
-            // stop collecting PRs for the previous version
-            //
-            // todo: this doesn't work for the workflows where we migrate changes onto stable branches
-            // while also simultaneously working on the next version in main
-            // I think all we need to do is just mark if this PR was backported and then provide that
-            // as a filter option
-            let end_id = repo.head().unwrap().target();
-            let commits = collect_pr_between(repo, end_id.unwrap(), start_id);
-            patch_versions.push(PatchVersionChanged {
-                commits,
-                version: patch_version,
-                published: false,
-            });
-            break;
-        }
+        // If there's no next minor version, then the remaining tags are for *this* version
+        // The idea being that once breaking changes exist in the form of a new minor version, we
+        // stop collecting PRs for the previous version
+        //
+        // Fixes that get cherry-picked onto a stable branch while main moves on are marked with
+        // `backported_to` instead, which the UI can filter on
+        //
+        // The last tag is usually on that stable branch rather than on main, so the walk from HEAD
+        // stops where main branched off instead of running all the way to the root commit
+        let end_id = repo.head()?.peel_to_commit()?.id();
+        let commits =
+            collect_pr_between(repo, config, backports, cache, diagnostics, end_id, last.id)?;
+
+        // After a prerelease the next version is the release itself, otherwise it's the next patch
+        let version = match last.pre() {
+            Some(_) => last.version.patch,
+            None => last.version.patch + 1,
+        };
 
-        let commits = collect_pr_between(repo, end_id.unwrap(), start_id);
-        patch_versions.push(PatchVersionChanged {
+        patch_versions.push(patch_version_changed(
+            repo,
+            end_id,
             commits,
-            version: patch_version,
-            published: true,
-        });
-
-        patch_version += 1;
+            version as usize,
+            None,
+            false,
+        ));
     }
 
-    MinorVersionChanged {
+    Ok(MinorVersionChanged {
         patch_versions,
-        version: minor_version,
-    }
+        major: last.version.major as usize,
+        version: last.version.minor as usize,
+    })
 }
 
-#[test]
-fn collects_prs_for_5() {
-    let repo = Repository::open(
-        "/Users/jonkelley/Development/dioxus"
-            .parse::<PathBuf>()
-            .unwrap(),
-    )
-    .unwrap();
-    collect_prs_for_minor_version(&repo, 5);
-}
+/// Build the entry for a single version
+///
+/// Uses the workspace dependency graph at `release` (the tag, or HEAD if unpublished) to fill in the
+/// crates that are affected by the changes through their dependencies. Together with the changed
+/// crates, that's the full set of crates that need to be published.
+fn patch_version_changed(
+    repo: &Repository,
+    release: Oid,
+    mut commits: Vec<PrCommit>,
+    version: usize,
+    pre: Option<String>,
+    published: bool,
+) -> PatchVersionChanged {
+    let workspace = repo
+        .find_commit(release)
+        .and_then(|commit| commit.tree())
+        .ok()
+        .and_then(|tree| Workspace::from_tree(repo, &tree))
+        .unwrap_or_default();
+
+    for commit in commits.iter_mut() {
+        commit.affected_packages = workspace.dependents(&commit.changed_packages);
+    }
 
-fn collect_pr_between(repo: &Repository, end_id: Oid, start_id: Oid) -> Vec<PrCommit> {
-    let mut prs = vec![];
+    let changed_packages: HashSet<String> = commits
+        .iter()
+        .flat_map(|commit| commit.changed_packages.iter().cloned())
+        .collect();
 
-    let mut revwalk = repo.revwalk().unwrap();
-    revwalk.push(end_id).unwrap();
-    _ = revwalk.simplify_first_parent();
+    let affected_packages = workspace.dependents(&changed_packages);
 
-    let mut commits = vec![];
+    PatchVersionChanged {
+        version,
+        pre,
+        published,
+        changed_packages,
+        affected_packages,
+        commits,
+    }
+}
+
+/// Collect the commits on the first-parent line of `end_id` that `start_id` doesn't have
+///
+/// `start_id` is the previous release, so neither it nor anything it was built on is included. If it lives
+/// on another branch, the walk stops where the two histories meet.
+fn collect_pr_between(
+    repo: &Repository,
+    config: &DoxieConfig,
+    backports: &BackportIndex,
+    cache: &mut CommitCache,
+    diagnostics: &mut Diagnostics,
+    end_id: Oid,
+    start_id: Oid,
+) -> Result<Vec<PrCommit>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(end_id)?;
+    revwalk.hide(start_id)?;
+    _ = revwalk.simplify_first_parent();
 
     // note that this actually walks backwards
-    for commit in revwalk {
-        let commit_id = commit.unwrap();
-        let commit = repo.find_commit(commit_id).unwrap();
-        let commit_id = commit.id();
+    let commits = revwalk
+        .map(|id| Ok(repo.find_commit(id?)?))
+        .collect::<Result<Vec<_>>>()?;
 
-        commits.push(commit);
+    let mut workspaces = WorkspaceCache::default();
+    let churn_filter = churn::ChurnFilter::new(&config.churn);
 
-        if commit_id == start_id {
-            break; // Stop when reaching the starting commit
-        }
-    }
+    let mut prs = vec![];
 
     // Now walk the merge commits and list out the files changed by that commit
-    // Diff that commit with its parent
+    // Commits we've diffed on a previous run come straight out of the cache
     for (idx, commit) in commits.iter().enumerate() {
-        let Ok(parent) = commit.parent(0) else {
-            continue;
+        let diffed = match cache.commit(commit.id()) {
+            Some(cached) => cached.clone(),
+            None => {
+                let diffed = diff_commit(repo, config, &mut workspaces, &churn_filter, commit);
+                let Some(Some(diffed)) =
+                    diagnostics.recover(format!("commit {}", commit.id()), diffed)?
+                else {
+                    continue;
+                };
+
+                cache.insert_commit(commit.id(), diffed.clone());
+                diffed
+            }
         };
 
-        let diff = repo
-            .diff_tree_to_tree(
-                Some(&parent.tree().unwrap()),
-                Some(&commit.tree().unwrap()),
-                None,
-            )
-            .unwrap();
+        // Neither of these has to be utf8, and one odd commit shouldn't end the walk
+        let message = String::from_utf8_lossy(commit.message_bytes());
+        let summary = String::from_utf8_lossy(commit.summary_bytes().unwrap_or_default());
+        let id = pr_refs::parse(&message).merged_pr(&config.main_repo);
+        let backported_to = backports.backported_to(commit.id(), id, diffed.patch_id());
+
+        prs.push(PrCommit {
+            summary: summary.to_string(),
+            id,
+            changed_packages: diffed.changed_packages,
+            // Filled in with the dependency graph of the release this commit ends up in
+            affected_packages: HashSet::new(),
+            affects_workspace: diffed.affects_workspace,
+            commit_hash: commit.id().to_string(),
+            backported_to,
+            // Running the checks means checking out every commit, so it's done as a separate pass
+            semver_findings: vec![],
+            linked_issues: linked_issues::from_text(&config.main_repo, &message),
+            churn: diffed.churn,
+            churn_by_crate: diffed.churn_by_crate,
+            head_index: idx,
+        });
+    }
 
-        let mut pr = Pr {
-            commit: commit.clone(),
-            changed_files: HashSet::new(),
-            id: None,
-            idx,
-        };
+    Ok(prs)
+}
 
-        for delta in diff.deltas() {
-            let old_file = delta.old_file();
-            let new_file = delta.new_file();
+/// Diff a commit with its parent and work out which crates it changed and by how much
+///
+/// Returns `None` for the root commit, which has no parent to diff against
+fn diff_commit(
+    repo: &Repository,
+    config: &DoxieConfig,
+    workspaces: &mut WorkspaceCache,
+    churn_filter: &churn::ChurnFilter,
+    commit: &Commit,
+) -> Result<Option<CachedCommit>> {
+    let Ok(parent) = commit.parent(0) else {
+        return Ok(None);
+    };
 
-            pr.changed_files
-                .insert(PathBuf::from(old_file.path().unwrap()));
-            pr.changed_files
-                .insert(PathBuf::from(new_file.path().unwrap()));
-        }
+    let old_tree = parent.tree()?;
+    let new_tree = commit.tree()?;
+
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+
+    // Removed files belong to the crate they were in before the change, so look at both sides
+    let old_workspace = workspaces.get(repo, &old_tree);
+    let new_workspace = workspaces.get(repo, &new_tree);
+
+    let mut pr = Pr {
+        changed_files: HashSet::new(),
+        workspace_crates: match old_workspace.is_some() || new_workspace.is_some() {
+            true => Some(HashSet::new()),
+            false => None,
+        },
+        affects_workspace: false,
+        patch_id: diff.patchid(None).ok(),
+        churn: ChurnStats::default(),
+        churn_by_crate: HashMap::new(),
+    };
 
-        let summary = commit.summary().unwrap();
+    for (delta_idx, delta) in diff.deltas().enumerate() {
+        // Added and removed files still carry the path on both sides
+        let old_path = delta.old_file().path().or(delta.new_file().path());
+        let new_path = delta.new_file().path().or(delta.old_file().path());
+        let (Some(old_file), Some(new_file)) = (old_path, new_path) else {
+            continue;
+        };
 
-        // usually a merge commit will contain the ID in the form of (#id) or "Merge pull request #id from user/branch"
-        // try and find the first match (from the end of the string) that matches that
-        // A bit stupid but look for the `#` character and then try and parse the number after it
-        // first one wins
-        for part in summary.split_ascii_whitespace().rev() {
-            if let Some(id) = part.strip_prefix("(#") {
-                pr.id = Some(id.trim_end_matches(")").parse().unwrap());
-                break;
+        let (old_file, new_file) = (old_file.to_path_buf(), new_file.to_path_buf());
+
+        for (workspace, file) in [(&old_workspace, &old_file), (&new_workspace, &new_file)] {
+            if workspace::is_workspace_file(file) {
+                pr.affects_workspace = true;
             }
 
-            if let Some(id) = part.strip_prefix("#") {
-                pr.id = Some(id.parse().unwrap());
-                break;
+            let krate = workspace.as_ref().and_then(|ws| ws.crate_for(file));
+            if let (Some(crates), Some(krate)) = (pr.workspace_crates.as_mut(), krate) {
+                crates.insert(krate.name.clone());
             }
         }
 
-        prs.push(pr);
+        if !churn_filter.is_excluded(&new_file) {
+            let churn = churn::file_churn(&diff, delta_idx);
+            pr.churn.add(&churn);
+
+            // A file that moved between crates counts towards the crate it ended up in
+            let krate = new_workspace
+                .as_ref()
+                .and_then(|ws| ws.crate_for(&new_file))
+                .or_else(|| {
+                    old_workspace
+                        .as_ref()
+                        .and_then(|ws| ws.crate_for(&old_file))
+                });
+
+            if let Some(krate) = krate {
+                pr.churn_by_crate
+                    .entry(krate.name.clone())
+                    .or_default()
+                    .add(&churn);
+            }
+        }
+
+        pr.changed_files.insert(old_file);
+        pr.changed_files.insert(new_file);
     }
 
-    prs.iter()
-        .map(|pr| PrCommit {
-            summary: pr.commit.summary().unwrap().to_string(),
-            id: pr.id,
-            changed_packages: pr.changed_packages().into_iter().collect(),
-            commit_hash: pr.commit.id().to_string(),
-            head_index: pr.idx,
-        })
-        .collect()
+    Ok(Some(CachedCommit {
+        changed_packages: pr
+            .changed_packages(&config.workspace.package_roots)
+            .into_iter()
+            .collect(),
+        affects_workspace: pr.affects_workspace,
+        patch_id: pr.patch_id.map(|id| id.to_string()),
+        churn: pr.churn,
+        churn_by_crate: pr.churn_by_crate,
+    }))
+}
+
+#[test]
+fn collects_prs_between_tags() {
+    let fixture = fixtures::dioxus_like();
+    let repo = &fixture.repo;
+    let config = DoxieConfig::default();
+    let mut cache = CommitCache::in_memory();
+    let tag_index = TagIndex::new(repo, &config.releases, &mut cache).unwrap();
+
+    let mut between = |start: &str, end: &str| {
+        let id = |version: &str| {
+            let version = semver::Version::parse(version).unwrap();
+            tag_index.get(&version).unwrap().id
+        };
+
+        collect_pr_between(
+            repo,
+            &config,
+            &BackportIndex::default(),
+            &mut cache,
+            &mut Diagnostics::new(false),
+            id(end),
+            id(start),
+        )
+        .unwrap()
+    };
+    let summaries = |commits: &[PrCommit]| -> Vec<String> {
+        commits
+            .iter()
+            .map(|commit| commit.summary.clone())
+            .collect()
+    };
+
+    // The start tag was already released, so every range only has the commits after it
+    let commits = between("0.4.0", "0.4.1");
+    assert_eq!(
+        summaries(&commits),
+        ["Release 0.4.1", "Fix the diffing of keyed lists (#101)"]
+    );
+    assert_eq!(commits[1].id, Some(101));
+
+    assert!(commits[0].affects_workspace);
+    assert!(commits[0].changed_packages.is_empty());
+    assert_eq!(
+        commits[1].changed_packages,
+        HashSet::from(["dioxus-core".to_string()])
+    );
+
+    // The merged branch is behind the second parent, so only the merge commit shows up
+    assert_eq!(
+        summaries(&between("0.4.1", "0.5.0")),
+        [
+            "Release 0.5.0",
+            "Merge pull request #102 from ealmloff/onmounted"
+        ]
+    );
+
+    assert_eq!(
+        summaries(&between("0.5.0", "0.5.1")),
+        ["Release 0.5.1", "Fix a panic in the web renderer (#103)"]
+    );
+
+    // v0.5.1 is on the stable branch, so main picks up from where the two split at v0.5.0
+    assert_eq!(
+        summaries(&between("0.5.1", "0.6.0-alpha.0")),
+        [
+            "Release 0.6.0-alpha.0",
+            "Rewrite the core scheduler (#104)",
+            "Fix a panic in the web renderer (#103)"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn collects_every_release_line() {
+    let fixture = fixtures::dioxus_like();
+    let out_dir = tempfile::tempdir().unwrap();
+    let config = DoxieConfig::default();
+
+    let collect = || async {
+        changed_crates_on_repo(
+            &fixtures::github(),
+            &fixture.repo,
+            fixture.repo.workdir().unwrap(),
+            &config,
+            out_dir.path(),
+            false,
+            false,
+            Diagnostics::new(false),
+        )
+        .await
+        .unwrap();
+
+        fixtures::read_blob(out_dir.path().join("commits.json"))
+    };
+
+    let blob = collect().await;
+
+    // The second run reads every commit back out of the cache and should come to the same result
+    assert!(out_dir.path().join("commit_cache.json").exists());
+    assert_eq!(collect().await, blob);
+
+    let changed: ChangedVersions = serde_json::from_value(blob.clone()).unwrap();
+    let mut lines: Vec<_> = changed.version.keys().map(String::as_str).collect();
+    lines.sort();
+    assert_eq!(lines, ["0.4", "0.5", "0.6"]);
+
+    // The prerelease gets its own entry and the commits after it are unpublished
+    let next = &changed.version["0.6"].patch_versions;
+    assert_eq!(next[0].pre.as_deref(), Some("alpha.0"));
+    assert!(!next[1].published);
+    assert_eq!(next[1].commits[0].id, Some(105));
+    assert_eq!(
+        next[1].affected_packages,
+        HashSet::new(),
+        "nothing depends on dioxus-web"
+    );
+
+    // The fix that was cherry-picked onto the stable branch knows where it went
+    let fix = next[0]
+        .commits
+        .iter()
+        .find(|commit| commit.id == Some(103))
+        .unwrap();
+    assert_eq!(fix.backported_to, ["v0.5.1"]);
+    assert_eq!(fix.linked_issues[0].number, 90);
+
+    // Changing dioxus-core means its dependents need a release too
+    let scheduler = next[0]
+        .commits
+        .iter()
+        .find(|commit| commit.id == Some(104))
+        .unwrap();
+    assert_eq!(
+        scheduler.affected_packages,
+        HashSet::from(["dioxus-html".to_string(), "dioxus-web".to_string()])
+    );
+
+    insta::assert_json_snapshot!(blob);
 }
 
 #[tokio::test]
-async fn changed_crates_between() {
-    collect_changed_crates("/Users/jonkelley/Development/dioxus".parse().unwrap()).await;
+async fn collects_unreleased_commits_after_a_stable_release() {
+    // The newest tag of the line is v0.5.1, which only exists on the stable branch
+    let fixture = fixtures::dioxus_before_prerelease();
+    let out_dir = tempfile::tempdir().unwrap();
+
+    // A second tag for the same release shouldn't change anything but the diagnostics
+    let release = fixture.repo.revparse_single("v0.5.1").unwrap();
+    fixture.tag("v0.5.1+hotfix", release.peel_to_commit().unwrap().id());
+
+    changed_crates_on_repo(
+        &fixtures::github(),
+        &fixture.repo,
+        fixture.repo.workdir().unwrap(),
+        &DoxieConfig::default(),
+        out_dir.path(),
+        false,
+        false,
+        Diagnostics::new(false),
+    )
+    .await
+    .unwrap();
+
+    let blob = fixtures::read_blob(out_dir.path().join("commits.json"));
+    let changed: ChangedVersions = serde_json::from_value(blob).unwrap();
+
+    let line = &changed.version["0.5"].patch_versions;
+    let summaries = |index: usize| -> Vec<_> {
+        line[index]
+            .commits
+            .iter()
+            .map(|commit| commit.summary.as_str())
+            .collect()
+    };
+
+    assert_eq!(line.len(), 3);
+    assert_eq!(
+        summaries(1),
+        ["Release 0.5.1", "Fix a panic in the web renderer (#103)"]
+    );
+
+    // Only what landed on main since it branched off for v0.5, not all of history
+    assert_eq!((line[2].version, line[2].published), (2, false));
+    assert_eq!(
+        summaries(2),
+        [
+            "Rewrite the core scheduler (#104)",
+            "Fix a panic in the web renderer (#103)"
+        ]
+    );
+    assert_eq!(line[2].commits[1].backported_to, ["v0.5.1"]);
+
+    assert_eq!(
+        changed.diagnostics,
+        [Diagnostic {
+            scope: "tag v0.5.1+hotfix".to_string(),
+            message: "0.5.1 is already tagged as v0.5.1".to_string(),
+        }]
+    );
 }
 
 struct CrateVersion {
@@ -0,0 +1,338 @@
+//! Compare the benchmarks of two commits on the same runner
+//!
+//! A walltime from one CI runner says little about one from another - they differ by more than most
+//! regressions do. Instead we check both commits out into their own worktree and run their benchmarks in
+//! alternating rounds, so whatever else the machine is up to slows both sides down about equally. Every
+//! round gives one mean per benchmark per commit, and a Welch's t-test over those tells us whether the
+//! difference is bigger than the noise between rounds.
+
+use std::{collections::HashMap, f64::consts::PI, path::Path};
+
+use doxie_types::*;
+
+use crate::{
+    benchmarks,
+    error::{Diagnostics, DoxieError, Result},
+    semver_checks::Worktree,
+};
+
+/// Run the benchmarks of both commits in interleaved rounds and compare them
+///
+/// Also hands back the stats of the candidate's last round, with their walltime normalized against the
+/// baseline
+pub async fn compare(
+    repo_path: &Path,
+    baseline: &str,
+    candidate: &str,
+    config: &BenchConfig,
+    diagnostics: &mut Diagnostics,
+) -> Result<(PerfComparison, HashMap<String, PerfStats>)> {
+    // Comparing a commit against itself can only ever come out unchanged
+    if baseline == candidate {
+        return Err(DoxieError::SameBaseline(baseline.to_string()));
+    }
+
+    let baseline_tree = check_out(repo_path, baseline).await?;
+    let candidate_tree = check_out(repo_path, candidate).await?;
+
+    let mut baseline_rounds: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut candidate_rounds: HashMap<String, Vec<f64>> = HashMap::new();
+    let mut candidate_perf = HashMap::new();
+
+    for round in 0..config.rounds {
+        // Swap who goes first every round so neither side always gets the warmer machine
+        let candidate_first = round % 2 == 1;
+
+        for is_candidate in [candidate_first, !candidate_first] {
+            let (tree, rounds) = match is_candidate {
+                true => (&candidate_tree, &mut candidate_rounds),
+                false => (&baseline_tree, &mut baseline_rounds),
+            };
+
+            println!(
+                "Round {}/{}: benchmarking the {}",
+                round + 1,
+                config.rounds,
+                if is_candidate {
+                    "candidate"
+                } else {
+                    "baseline"
+                }
+            );
+
+            let perf = benchmarks::run(&tree.path, config, diagnostics).await?;
+            for (name, stats) in perf.iter() {
+                rounds
+                    .entry(name.clone())
+                    .or_default()
+                    .push(stats.raw_walltime);
+            }
+
+            if is_candidate {
+                candidate_perf = perf;
+            }
+        }
+    }
+
+    let benchmarks = compare_rounds(&baseline_rounds, &candidate_rounds, config);
+
+    for change in benchmarks.iter() {
+        if let Some(stats) = candidate_perf.get_mut(&change.name) {
+            stats.normalized_walltime = Some(change.ratio);
+        }
+    }
+
+    let comparison = PerfComparison {
+        baseline: baseline.to_string(),
+        candidate: candidate.to_string(),
+        rounds: config.rounds,
+        threshold: config.threshold,
+        significance: config.significance,
+        benchmarks,
+    };
+
+    Ok((comparison, candidate_perf))
+}
+
+async fn check_out(repo_path: &Path, commit: &str) -> Result<Worktree> {
+    Worktree::new(repo_path, commit)
+        .await
+        .ok_or_else(|| DoxieError::Worktree(commit.to_string()))
+}
+
+/// Compare the per-round walltimes of every benchmark both sides have
+///
+/// Benchmarks that were added or removed between the commits have nothing to compare against, so
+/// they're left out
+pub fn compare_rounds(
+    baseline: &HashMap<String, Vec<f64>>,
+    candidate: &HashMap<String, Vec<f64>>,
+    config: &BenchConfig,
+) -> Vec<BenchmarkChange> {
+    let mut changes: Vec<_> = candidate
+        .iter()
+        .filter_map(|(name, candidate_rounds)| {
+            let baseline_rounds = baseline.get(name)?;
+
+            let ratio = mean(candidate_rounds) / mean(baseline_rounds);
+            let p_value = welch_t_test(baseline_rounds, candidate_rounds);
+
+            let verdict =
+                if p_value >= config.significance || (ratio - 1.0).abs() <= config.threshold {
+                    PerfVerdict::Unchanged
+                } else if ratio > 1.0 {
+                    PerfVerdict::Regression
+                } else {
+                    PerfVerdict::Improvement
+                };
+
+            Some(BenchmarkChange {
+                name: name.clone(),
+                baseline_rounds: baseline_rounds.clone(),
+                candidate_rounds: candidate_rounds.clone(),
+                ratio,
+                p_value,
+                verdict,
+            })
+        })
+        .collect();
+
+    changes.sort_by(|a, b| a.name.cmp(&b.name));
+    changes
+}
+
+fn mean(samples: &[f64]) -> f64 {
+    samples.iter().sum::<f64>() / samples.len() as f64
+}
+
+/// The sample variance, with Bessel's correction
+fn variance(samples: &[f64]) -> f64 {
+    let mean = mean(samples);
+    samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (samples.len() as f64 - 1.0)
+}
+
+/// The two-sided p-value of Welch's t-test, which doesn't assume both sides are equally noisy
+fn welch_t_test(a: &[f64], b: &[f64]) -> f64 {
+    // One round doesn't say anything about the noise
+    if a.len() < 2 || b.len() < 2 {
+        return 1.0;
+    }
+
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let (se_a, se_b) = (variance(a) / n_a, variance(b) / n_b);
+    let se = se_a + se_b;
+
+    if se == 0.0 {
+        return if mean(a) == mean(b) { 1.0 } else { 0.0 };
+    }
+
+    let t = (mean(a) - mean(b)) / se.sqrt();
+
+    // The Welch–Satterthwaite approximation of the degrees of freedom
+    let df = se.powi(2) / (se_a.powi(2) / (n_a - 1.0) + se_b.powi(2) / (n_b - 1.0));
+
+    student_t_p_value(t, df)
+}
+
+/// The chance of a t statistic at least this far from zero, in either direction
+fn student_t_p_value(t: f64, df: f64) -> f64 {
+    incomplete_beta(df / 2.0, 0.5, df / (df + t * t))
+}
+
+/// The regularized incomplete beta function `I_x(a, b)`, as in Numerical Recipes
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+
+    // The continued fraction converges quickly on this side, so use the symmetry for the other one
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_fraction(a, b, x) / a
+    } else {
+        1.0 - front * beta_fraction(b, a, 1.0 - x) / b
+    }
+}
+
+/// The continued fraction of the incomplete beta function, with the modified Lentz method
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64 {
+    const TINY: f64 = 1e-30;
+    let not_tiny = |value: f64| if value.abs() < TINY { TINY } else { value };
+
+    let mut c = 1.0;
+    let mut d = 1.0 / not_tiny(1.0 - (a + b) * x / (a + 1.0));
+    let mut h = d;
+
+    for m in 1..300 {
+        let m = m as f64;
+
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 / not_tiny(1.0 + even * d);
+        c = not_tiny(1.0 + even / c);
+        h *= d * c;
+
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 / not_tiny(1.0 + odd * d);
+        c = not_tiny(1.0 + odd / c);
+        h *= d * c;
+
+        if (d * c - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+
+    h
+}
+
+/// The log of the gamma function, with the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+
+    if x < 0.5 {
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[tokio::test]
+async fn refuses_to_compare_a_commit_with_itself() {
+    let sha = "c08f514e6b1fd2d1bb1a7bdc5b5b1b4e7f1c2a3d";
+    let result = compare(
+        ".".as_ref(),
+        sha,
+        sha,
+        &BenchConfig::default(),
+        &mut Diagnostics::new(false),
+    )
+    .await;
+
+    assert!(matches!(result, Err(DoxieError::SameBaseline(_))));
+}
+
+#[test]
+fn matches_the_t_table() {
+    // The two-sided 5% critical values of the t distribution
+    for (t, df) in [(12.706, 1.0), (2.306, 8.0), (2.042, 30.0)] {
+        let p = student_t_p_value(t, df);
+        assert!((p - 0.05).abs() < 1e-3, "p = {p} for t = {t} with {df} df");
+    }
+
+    assert_eq!(student_t_p_value(0.0, 8.0), 1.0);
+    assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-10);
+}
+
+#[test]
+fn only_reports_significant_changes_over_the_threshold() {
+    let config = BenchConfig::default();
+    let rounds = |samples: &[(&str, &[f64])]| -> HashMap<String, Vec<f64>> {
+        samples
+            .iter()
+            .map(|(name, rounds)| (name.to_string(), rounds.to_vec()))
+            .collect()
+    };
+
+    let baseline = rounds(&[
+        ("create_rows", &[100.0, 101.0, 99.0, 100.0, 100.5]),
+        ("diff", &[200.0, 202.0, 198.0, 201.0, 199.0]),
+        ("noisy", &[100.0, 150.0, 80.0, 130.0, 90.0]),
+        ("steady", &[100.0, 100.1, 99.9, 100.0, 100.0]),
+        ("removed", &[10.0, 10.0, 10.0, 10.0, 10.0]),
+    ]);
+    let candidate = rounds(&[
+        // 20% slower, well outside the noise
+        ("create_rows", &[120.0, 121.0, 119.0, 120.0, 120.5]),
+        // 10% faster
+        ("diff", &[180.0, 181.0, 179.0, 182.0, 178.0]),
+        // 10% slower on average, but the rounds are all over the place
+        ("noisy", &[110.0, 165.0, 88.0, 143.0, 99.0]),
+        // Significant, but only 1% slower
+        ("steady", &[101.0, 101.1, 100.9, 101.0, 101.0]),
+        ("added", &[10.0, 10.0, 10.0, 10.0, 10.0]),
+    ]);
+
+    let changes = compare_rounds(&baseline, &candidate, &config);
+    let verdicts: Vec<_> = changes
+        .iter()
+        .map(|change| (change.name.as_str(), change.verdict))
+        .collect();
+
+    assert_eq!(
+        verdicts,
+        [
+            ("create_rows", PerfVerdict::Regression),
+            ("diff", PerfVerdict::Improvement),
+            ("noisy", PerfVerdict::Unchanged),
+            ("steady", PerfVerdict::Unchanged),
+        ]
+    );
+
+    assert!((changes[0].ratio - 1.2).abs() < 1e-3);
+    assert!(changes[0].p_value < 1e-6);
+    assert!(changes[2].p_value > config.significance);
+    assert!(changes[3].p_value < config.significance);
+}
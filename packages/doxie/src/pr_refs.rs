@@ -0,0 +1,241 @@
+//! Pull references to PRs and issues out of commit messages
+//!
+//! Commits land on main in a couple of different shapes: squash merges end their summary with `(#123)`,
+//! merge commits start with `Merge pull request #123 from user/branch`, and the body can close issues
+//! with `Closes #45` or credit people with `Co-authored-by` trailers. None of this is guaranteed to be
+//! well formed, so anything that doesn't parse is just skipped.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+/// A reference to a PR or issue, ie `#123` or `DioxusLabs/blitz#45`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PrRef {
+    /// The `owner/repo` the reference points at, `None` if it's in the same repo as the commit
+    pub repo: Option<String>,
+
+    pub number: usize,
+}
+
+impl PrRef {
+    /// Check if this reference points into the given `owner/repo`
+    pub fn is_in(&self, repo: &str) -> bool {
+        match &self.repo {
+            Some(other) => other.eq_ignore_ascii_case(repo),
+            None => true,
+        }
+    }
+}
+
+/// Someone credited with a `Co-authored-by` trailer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoAuthor {
+    pub name: String,
+    pub email: String,
+}
+
+/// Everything we could find in a single commit message
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrRefs {
+    /// The PR the commit was merged from, from the `(#123)` squash suffix or the merge commit summary
+    pub merged_from: Option<PrRef>,
+
+    /// Issues closed with a keyword like `Closes #45` or `fixes DioxusLabs/blitz#12`
+    pub closes: Vec<PrRef>,
+
+    /// Any other references in the message
+    pub mentions: Vec<PrRef>,
+
+    pub co_authors: Vec<CoAuthor>,
+}
+
+impl PrRefs {
+    /// The number of the PR the commit was merged from, as long as it's a PR of the given `owner/repo`
+    pub fn merged_pr(&self, repo: &str) -> Option<usize> {
+        self.merged_from
+            .as_ref()
+            .filter(|pr| pr.is_in(repo))
+            .map(|pr| pr.number)
+    }
+}
+
+/// A `#123`, `owner/repo#123`, or a full github url to an issue or PR
+const REFERENCE: &str = r"(?:https://github\.com/(?P<url_repo>[\w.-]+/[\w.-]+)/(?:issues|pull)/(?P<url_number>\d+)|(?:\b(?P<repo>[\w.-]+/[\w.-]+))?#(?P<number>\d+)\b)";
+
+/// Parse all the references out of a full commit message
+pub fn parse(message: &str) -> PrRefs {
+    static SQUASH: OnceLock<Regex> = OnceLock::new();
+    static MERGE: OnceLock<Regex> = OnceLock::new();
+    static CLOSES: OnceLock<Regex> = OnceLock::new();
+    static ANY: OnceLock<Regex> = OnceLock::new();
+    static CO_AUTHOR: OnceLock<Regex> = OnceLock::new();
+
+    let squash = SQUASH
+        .get_or_init(|| Regex::new(r"\((?:(?P<repo>[\w.-]+/[\w.-]+))?#(?P<number>\d+)\)").unwrap());
+    let merge = MERGE.get_or_init(|| {
+        Regex::new(r"^Merge pull request (?:(?P<repo>[\w.-]+/[\w.-]+))?#(?P<number>\d+)").unwrap()
+    });
+    let closes = CLOSES.get_or_init(|| {
+        Regex::new(&format!(
+            r"(?i)\b(?:close[sd]?|fix(?:e[sd])?|resolve[sd]?):?\s+{REFERENCE}"
+        ))
+        .unwrap()
+    });
+    let any = ANY.get_or_init(|| Regex::new(REFERENCE).unwrap());
+    let co_author = CO_AUTHOR.get_or_init(|| {
+        Regex::new(r"(?im)^\s*co-authored-by:\s*(?P<name>.*?)\s*<(?P<email>[^>]+)>\s*$").unwrap()
+    });
+
+    let summary = message.lines().next().unwrap_or_default();
+
+    // The squash suffix is always the last one, in case the title mentions another PR
+    let merged_from = match merge.captures(summary) {
+        Some(captures) => reference(&captures),
+        None => squash
+            .captures_iter(summary)
+            .filter_map(|captures| reference(&captures))
+            .last(),
+    };
+
+    let mut refs = PrRefs {
+        merged_from,
+        ..Default::default()
+    };
+
+    for captures in closes.captures_iter(message) {
+        if let Some(closed) = reference(&captures) {
+            if !refs.closes.contains(&closed) {
+                refs.closes.push(closed);
+            }
+        }
+    }
+
+    for captures in any.captures_iter(message) {
+        let Some(mention) = reference(&captures) else {
+            continue;
+        };
+
+        let known = refs.merged_from.as_ref() == Some(&mention)
+            || refs.closes.contains(&mention)
+            || refs.mentions.contains(&mention);
+
+        if !known {
+            refs.mentions.push(mention);
+        }
+    }
+
+    refs.co_authors = co_author
+        .captures_iter(message)
+        .map(|captures| CoAuthor {
+            name: captures["name"].to_string(),
+            email: captures["email"].to_string(),
+        })
+        .collect();
+
+    refs
+}
+
+/// Build a reference from the named groups of a match
+///
+/// Numbers that don't fit are skipped instead of panicking
+fn reference(captures: &regex::Captures) -> Option<PrRef> {
+    let number = captures
+        .name("number")
+        .or_else(|| captures.name("url_number"))?
+        .as_str()
+        .parse()
+        .ok()?;
+
+    let repo = captures
+        .name("repo")
+        .or_else(|| captures.name("url_repo"))
+        .map(|repo| repo.as_str().to_string());
+
+    Some(PrRef { repo, number })
+}
+
+#[test]
+fn parses_real_commit_messages() {
+    let local = |number| PrRef { repo: None, number };
+    let remote = |repo: &str, number| PrRef {
+        repo: Some(repo.to_string()),
+        number,
+    };
+
+    /// (message, merged from, closes, mentions)
+    type Row = (&'static str, Option<PrRef>, Vec<PrRef>, Vec<PrRef>);
+
+    let table: Vec<Row> = vec![
+        (
+            "Fix hot reloading on windows (#2142)",
+            Some(local(2142)),
+            vec![],
+            vec![],
+        ),
+        (
+            "Merge pull request #1234 from ealmloff/fix-signals\n\nFix signals in async tasks",
+            Some(local(1234)),
+            vec![],
+            vec![],
+        ),
+        (
+            "Fix #12: crash when dropping a scope",
+            None,
+            vec![local(12)],
+            vec![],
+        ),
+        ("Bump dependencies (#123),", Some(local(123)), vec![], vec![]),
+        (
+            "Revert \"Add spread attributes (#1200)\" (#1255)",
+            Some(local(1255)),
+            vec![],
+            vec![local(1200)],
+        ),
+        (
+            "Update the router guide (DioxusLabs/docsite#45)",
+            Some(remote("DioxusLabs/docsite", 45)),
+            vec![],
+            vec![],
+        ),
+        (
+            "Make the cli config optional (#2039)\n\n* Closes #2001\n* fixes: DioxusLabs/blitz#33\n* see #1999 and https://github.com/DioxusLabs/dioxus/issues/1998",
+            Some(local(2039)),
+            vec![local(2001), remote("DioxusLabs/blitz", 33)],
+            vec![local(1999), remote("DioxusLabs/dioxus", 1998)],
+        ),
+        ("Release 0.5.0", None, vec![], vec![]),
+        ("Set the color to #fff (#99999999999999999999999)", None, vec![], vec![]),
+        ("Link to the guide#section (#2201)", Some(local(2201)), vec![], vec![]),
+    ];
+
+    for (message, merged_from, closes, mentions) in table {
+        let refs = parse(message);
+        assert_eq!(refs.merged_from, merged_from, "{message}");
+        assert_eq!(refs.closes, closes, "{message}");
+        assert_eq!(refs.mentions, mentions, "{message}");
+    }
+
+    let refs = parse(
+        "Add the fullstack router (#2155)\n\nCo-authored-by: Evan Almloff <evanalmloff@gmail.com>\nco-authored-by: Jonathan Kelley <jkelleyrtp@gmail.com>",
+    );
+    assert_eq!(
+        refs.co_authors,
+        vec![
+            CoAuthor {
+                name: "Evan Almloff".to_string(),
+                email: "evanalmloff@gmail.com".to_string(),
+            },
+            CoAuthor {
+                name: "Jonathan Kelley".to_string(),
+                email: "jkelleyrtp@gmail.com".to_string(),
+            },
+        ]
+    );
+
+    assert_eq!(refs.merged_pr("DioxusLabs/dioxus"), Some(2155));
+    assert_eq!(
+        parse("Update the router guide (DioxusLabs/docsite#45)").merged_pr("DioxusLabs/dioxus"),
+        None
+    );
+}
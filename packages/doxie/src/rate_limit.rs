@@ -0,0 +1,220 @@
+//! Keep the bot inside github's rate limit, and keep track of what each collector spends of it
+//!
+//! Every response says how many requests are left in the hour and when the budget resets. Once we're
+//! down to the reserve in the config we wait for the reset instead of running dry halfway through a
+//! run. If github refuses a request anyway - the secondary limits kick in on bursts - we back off for
+//! as long as it asks and try again.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use http::{HeaderMap, StatusCode};
+
+/// How much of the hourly budget is left, as of the last response
+#[derive(Debug)]
+pub struct RateLimit {
+    reserve: u64,
+    remaining: Option<u64>,
+
+    /// When the budget resets, in seconds since the unix epoch
+    reset: Option<u64>,
+}
+
+impl RateLimit {
+    pub fn new(reserve: u64) -> Self {
+        Self {
+            reserve,
+            remaining: None,
+            reset: None,
+        }
+    }
+
+    /// Remember what the headers of a response said about the budget
+    pub fn update(&mut self, headers: &HeaderMap) {
+        // The search api has its own, much smaller limit that resets every minute
+        let resource = headers
+            .get("x-ratelimit-resource")
+            .and_then(|value| value.to_str().ok());
+        if resource.is_some_and(|resource| resource != "core") {
+            return;
+        }
+
+        if let Some(remaining) = number(headers, "x-ratelimit-remaining") {
+            self.remaining = Some(remaining);
+        }
+        if let Some(reset) = number(headers, "x-ratelimit-reset") {
+            self.reset = Some(reset);
+        }
+    }
+
+    /// How long to wait before the next request, if we're down to the reserve
+    pub fn pause(&self, now: u64) -> Option<Duration> {
+        let (remaining, reset) = (self.remaining?, self.reset?);
+        (remaining <= self.reserve && reset > now).then(|| until(reset, now))
+    }
+
+    /// How long to back off after github refused a request for going over a limit, `None` if it didn't
+    pub fn backoff(status: StatusCode, headers: &HeaderMap, now: u64) -> Option<Duration> {
+        if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+            return None;
+        }
+
+        // The secondary limits say how long they want us to wait
+        if let Some(seconds) = number(headers, "retry-after") {
+            return Some(Duration::from_secs(seconds));
+        }
+
+        // A 403 is also what we get for missing permissions, which waiting won't fix
+        match number(headers, "x-ratelimit-remaining") {
+            Some(0) => number(headers, "x-ratelimit-reset").map(|reset| until(reset, now)),
+            _ => None,
+        }
+    }
+}
+
+/// Seconds since the unix epoch, which is what the reset header is in
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The time until a reset, with a second of slack so we don't wake up just before it
+fn until(reset: u64, now: u64) -> Duration {
+    Duration::from_secs(reset.saturating_sub(now) + 1)
+}
+
+fn number(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
+
+/// The requests each collector made over a run
+#[derive(Debug, Default)]
+pub struct Usage {
+    collector: Option<&'static str>,
+    collectors: BTreeMap<&'static str, CollectorUsage>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CollectorUsage {
+    pub requests: u64,
+
+    /// The requests answered with a `304`, which didn't cost anything
+    pub not_modified: u64,
+
+    /// How long we spent waiting on the rate limit
+    pub waited: Duration,
+}
+
+impl Usage {
+    /// Count everything from here on towards this collector
+    pub fn start(&mut self, collector: &'static str) {
+        self.collector = Some(collector);
+    }
+
+    pub fn record_request(&mut self, not_modified: bool) {
+        let usage = self.current();
+        usage.requests += 1;
+        usage.not_modified += not_modified as u64;
+    }
+
+    pub fn record_wait(&mut self, waited: Duration) {
+        self.current().waited += waited;
+    }
+
+    /// One line per collector that talked to github
+    pub fn summary(&self) -> String {
+        let mut summary = String::from("GitHub requests by collector:\n");
+
+        for (collector, usage) in &self.collectors {
+            summary.push_str(&format!(
+                "  {collector}: {} requests ({} not modified), waited {}s\n",
+                usage.requests,
+                usage.not_modified,
+                usage.waited.as_secs()
+            ));
+        }
+
+        summary
+    }
+
+    fn current(&mut self) -> &mut CollectorUsage {
+        // Anything before the first collector is the setup of the run
+        let collector = self.collector.unwrap_or("setup");
+        self.collectors.entry(collector).or_default()
+    }
+}
+
+#[test]
+fn waits_for_the_reset_near_the_limit() {
+    let headers = |pairs: &[(&'static str, &str)]| {
+        let mut headers = HeaderMap::new();
+        for &(name, value) in pairs {
+            headers.insert(name, value.parse().unwrap());
+        }
+        headers
+    };
+
+    let mut limit = RateLimit::new(100);
+    assert_eq!(limit.pause(1_000), None);
+
+    limit.update(&headers(&[
+        ("x-ratelimit-remaining", "101"),
+        ("x-ratelimit-reset", "1060"),
+    ]));
+    assert_eq!(limit.pause(1_000), None);
+
+    limit.update(&headers(&[
+        ("x-ratelimit-remaining", "100"),
+        ("x-ratelimit-reset", "1060"),
+    ]));
+    assert_eq!(limit.pause(1_000), Some(Duration::from_secs(61)));
+
+    // Once the reset has passed there's nothing to wait for
+    assert_eq!(limit.pause(1_060), None);
+
+    // The search budget doesn't say anything about the core one
+    limit.update(&headers(&[
+        ("x-ratelimit-resource", "search"),
+        ("x-ratelimit-remaining", "1000"),
+    ]));
+    assert_eq!(limit.pause(1_000), Some(Duration::from_secs(61)));
+
+    let secondary = headers(&[("retry-after", "30")]);
+    let exhausted = headers(&[
+        ("x-ratelimit-remaining", "0"),
+        ("x-ratelimit-reset", "1010"),
+    ]);
+    let forbidden = headers(&[("x-ratelimit-remaining", "4000")]);
+    assert_eq!(
+        RateLimit::backoff(StatusCode::TOO_MANY_REQUESTS, &secondary, 1_000),
+        Some(Duration::from_secs(30))
+    );
+    assert_eq!(
+        RateLimit::backoff(StatusCode::FORBIDDEN, &exhausted, 1_000),
+        Some(Duration::from_secs(11))
+    );
+    assert_eq!(
+        RateLimit::backoff(StatusCode::FORBIDDEN, &forbidden, 1_000),
+        None
+    );
+    assert_eq!(RateLimit::backoff(StatusCode::OK, &exhausted, 1_000), None);
+}
+
+#[test]
+fn counts_requests_per_collector() {
+    let mut usage = Usage::default();
+    usage.record_request(false);
+    usage.start("open_prs");
+    usage.record_request(false);
+    usage.record_request(true);
+    usage.record_wait(Duration::from_secs(5));
+
+    assert_eq!(
+        usage.summary(),
+        "GitHub requests by collector:\n  open_prs: 2 requests (1 not modified), waited 5s\n  setup: 1 requests (0 not modified), waited 0s\n"
+    );
+}
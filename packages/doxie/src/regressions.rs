@@ -0,0 +1,250 @@
+//! Find the commits where a benchmark stepped to a new level in the history of a branch
+//!
+//! Every commit that runs `doxie stats` adds its [`CommitStats`] to the history in `stats_history.json`.
+//! Looking at one benchmark across that history, we slide a window of commits before and after every
+//! point and compare their medians. The point counts as a step if the medians are further apart than the
+//! threshold and than a few median absolute deviations of either window, which is how much the runs of a
+//! steady benchmark usually wander. Medians ignore a single noisy run, so only a change that sticks for
+//! a whole window gets reported.
+
+use std::path::Path;
+
+use doxie_types::*;
+
+use crate::{
+    error::{DoxieError, Result},
+    pr_refs,
+};
+
+pub const HISTORY_FILE: &str = "stats_history.json";
+
+/// Load the stats history from the output dir, starting an empty one if there isn't one yet
+pub fn load_history(out_dir: &Path) -> Result<Fullstats> {
+    let path = out_dir.join(HISTORY_FILE);
+
+    match std::fs::read_to_string(&path) {
+        Ok(raw) => Ok(serde_json::from_str(&raw)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Fullstats::default()),
+        Err(err) => Err(DoxieError::io("read", &path)(err)),
+    }
+}
+
+/// Look for steps in every benchmark of every branch
+///
+/// `repo` is the `owner/repo` the commits were merged into, to tell which PR they came from
+pub fn detect(history: &Fullstats, config: &BenchConfig, repo: &str) -> RegressionReport {
+    let mut change_points = vec![];
+
+    for branch in history.stats.values() {
+        let mut benchmarks: Vec<_> = branch
+            .stats
+            .iter()
+            .flat_map(|commit| commit.perf.keys())
+            .collect();
+        benchmarks.sort();
+        benchmarks.dedup();
+
+        for benchmark in benchmarks {
+            // Not every commit ran every benchmark, so keep track of where each point came from
+            let (commits, series): (Vec<_>, Vec<_>) = branch
+                .stats
+                .iter()
+                .filter_map(|commit| Some((commit, commit.perf.get(benchmark)?.raw_walltime)))
+                .unzip();
+
+            for step in find_steps(&series, config) {
+                let commit = commits[step.index];
+                let ratio = step.after / step.before;
+
+                change_points.push(ChangePoint {
+                    branch: branch.name.clone(),
+                    benchmark: benchmark.clone(),
+                    sha: commit.sha.clone(),
+                    pr_name: commit.pr_name.clone(),
+                    pr: pr_refs::parse(&commit.pr_name).merged_pr(repo),
+                    before: step.before,
+                    after: step.after,
+                    ratio,
+                    verdict: match ratio > 1.0 {
+                        true => PerfVerdict::Regression,
+                        false => PerfVerdict::Improvement,
+                    },
+                });
+            }
+        }
+    }
+
+    change_points.sort_by(|a, b| (&a.branch, &a.benchmark).cmp(&(&b.branch, &b.benchmark)));
+
+    RegressionReport { change_points }
+}
+
+/// A point in a series where the level changed
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Step {
+    /// The first point of the new level
+    index: usize,
+    before: f64,
+    after: f64,
+}
+
+/// Find the steps in a series, in order
+fn find_steps(series: &[f64], config: &BenchConfig) -> Vec<Step> {
+    let window = config.history_window.max(1);
+
+    // A step pulls apart the windows of the points next to it too, so they come in runs
+    let mut runs: Vec<Vec<usize>> = vec![];
+    for index in window..=series.len().saturating_sub(window) {
+        let (before, after) = (
+            median(&series[index - window..index]),
+            median(&series[index..index + window]),
+        );
+        let noise = mad(&series[index - window..index]).max(mad(&series[index..index + window]));
+
+        let jump = (after - before).abs();
+        if jump <= config.step_mads * noise || jump <= config.threshold * before {
+            continue;
+        }
+
+        match runs.last_mut() {
+            Some(run) if index - run[run.len() - 1] < window => run.push(index),
+            _ => runs.push(vec![index]),
+        }
+    }
+
+    runs.into_iter()
+        .map(|run| {
+            // Split the stretch the run covers where two flat levels fit it best
+            let span = run[0] - window..run[run.len() - 1] + window;
+            let cost = |split: usize| {
+                deviation(&series[span.start..split]) + deviation(&series[split..span.end])
+            };
+            let index = run
+                .into_iter()
+                .min_by(|a, b| cost(*a).total_cmp(&cost(*b)))
+                .unwrap();
+
+            Step {
+                index,
+                before: median(&series[index - window..index]),
+                after: median(&series[index..index + window]),
+            }
+        })
+        .collect()
+}
+
+/// How far the samples are from their median, in total
+fn deviation(samples: &[f64]) -> f64 {
+    let median = median(samples);
+    samples.iter().map(|x| (x - median).abs()).sum()
+}
+
+fn median(samples: &[f64]) -> f64 {
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    let middle = sorted.len() / 2;
+    match sorted.len() % 2 {
+        0 => (sorted[middle - 1] + sorted[middle]) / 2.0,
+        _ => sorted[middle],
+    }
+}
+
+/// The median absolute deviation, scaled so it matches the standard deviation of normally distributed runs
+fn mad(samples: &[f64]) -> f64 {
+    let median = median(samples);
+    let deviations: Vec<_> = samples.iter().map(|x| (x - median).abs()).collect();
+    1.4826 * self::median(&deviations)
+}
+
+#[test]
+fn finds_lasting_steps_but_not_blips() {
+    let config = BenchConfig::default();
+
+    let noisy = [100.0, 102.0, 98.0, 101.0, 99.0, 100.0, 101.0, 99.0];
+    let mut series = noisy.to_vec();
+    // A single slow run on a busy runner
+    series[3] = 180.0;
+    // And then a real 20% regression
+    series.extend(noisy.iter().map(|x| x * 1.2));
+
+    let steps = find_steps(&series, &config);
+    assert_eq!(steps.len(), 1);
+    assert_eq!(steps[0].index, 8);
+    assert!((steps[0].after / steps[0].before - 1.2).abs() < 0.02);
+
+    // A 2% change is inside the threshold, even if the runs are perfectly steady
+    let small: Vec<_> = [100.0; 8].into_iter().chain([102.0; 8]).collect();
+    assert_eq!(find_steps(&small, &config), []);
+
+    // Only two runs since the regression, which could still be noise
+    assert_eq!(find_steps(&series[..10], &config), []);
+}
+
+#[test]
+fn reports_the_commit_and_pr_of_a_step() {
+    let mut history = Fullstats::default();
+
+    for i in 0..12 {
+        let walltime = if i < 6 { 100.0 } else { 80.0 };
+        history.record(
+            "main",
+            CommitStats {
+                pr_name: format!("Change number {i} (#{})", 100 + i),
+                sha: format!("sha{i}"),
+                commit_time: i,
+                perf: [(
+                    "diff".to_string(),
+                    PerfStats {
+                        raw_walltime: walltime,
+                        ..Default::default()
+                    },
+                )]
+                .into(),
+                ..Default::default()
+            },
+            100,
+        );
+    }
+
+    let report = detect(&history, &BenchConfig::default(), "dioxuslabs/dioxus");
+    assert_eq!(report.change_points.len(), 1);
+
+    let step = &report.change_points[0];
+    assert_eq!((step.sha.as_str(), step.pr), ("sha6", Some(106)));
+    assert_eq!(step.verdict, PerfVerdict::Improvement);
+    assert_eq!(step.ratio, 0.8);
+}
+
+#[test]
+fn keeps_the_history_in_order_and_capped() {
+    let mut history = Fullstats::default();
+    let stats = |sha: &str, commit_time| CommitStats {
+        sha: sha.to_string(),
+        commit_time,
+        ..Default::default()
+    };
+    let shas = |history: &Fullstats| -> Vec<String> {
+        history.stats["main"]
+            .stats
+            .iter()
+            .map(|stats| stats.sha.clone())
+            .collect()
+    };
+
+    for (sha, time) in [("a", 1), ("c", 3), ("d", 4)] {
+        history.record("main", stats(sha, time), 3);
+    }
+
+    // A CI job for an older commit that finished late goes where the commit landed
+    history.record("main", stats("b", 2), 4);
+    assert_eq!(shas(&history), ["a", "b", "c", "d"]);
+
+    // Rerunning a commit replaces its stats in place
+    history.record("main", stats("c", 3), 4);
+    assert_eq!(shas(&history), ["a", "b", "c", "d"]);
+
+    // Only the newest commits are kept
+    history.record("main", stats("e", 5), 3);
+    assert_eq!(shas(&history), ["c", "d", "e"]);
+}
@@ -0,0 +1,245 @@
+//! Keep track of which crates have changed since they were last published
+//!
+//! Every publishable crate in the workspace at HEAD is compared against the newest version in a local
+//! copy of the crates.io index. If commits touched the crate since that version, it needs a release.
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    path::Path,
+};
+
+use doxie_types::*;
+use git2::{Oid, Repository};
+
+use crate::{
+    backports::BackportIndex,
+    collect_pr_between,
+    commit_cache::CommitCache,
+    crates_index::CratesIndex,
+    error::{Diagnostics, DoxieError, Result},
+    releases::TagIndex,
+    workspace::{Workspace, WorkspaceCache, WorkspaceCrate},
+    write_blob,
+};
+
+pub fn collect_release_queue(
+    repo: &Repository,
+    config: &DoxieConfig,
+    index_path: &Path,
+    out_dir: &Path,
+    mut diagnostics: Diagnostics,
+) -> Result<()> {
+    let index = CratesIndex::new(index_path);
+    let mut cache = CommitCache::load(out_dir, config);
+    let tag_index = TagIndex::new(repo, &config.releases, &mut cache)?;
+    let backports = BackportIndex::new(repo, config, tag_index.lines())?;
+
+    let head = repo.head()?.peel_to_commit()?;
+    let workspace = Workspace::from_tree(repo, &head.tree()?).unwrap_or_default();
+
+    // Most crates get released together, so they share a baseline and we only walk it once
+    let mut commits_since: HashMap<Oid, Vec<PrCommit>> = HashMap::new();
+
+    let mut crates = vec![];
+
+    for krate in workspace.crates.iter().filter(|krate| krate.publish) {
+        let published = index.newest_version(&krate.name);
+
+        let baseline = published
+            .as_ref()
+            .and_then(|version| published_commit(repo, &tag_index, head.id(), krate, version));
+
+        // Whether we know what went into the published version, if there is one
+        let mut found_release = true;
+
+        let commits = match (baseline, &published) {
+            (Some(baseline), _) => {
+                let commits = match commits_since.entry(baseline) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => entry.insert(collect_pr_between(
+                        repo,
+                        config,
+                        &backports,
+                        &mut cache,
+                        &mut diagnostics,
+                        head.id(),
+                        baseline,
+                    )?),
+                };
+
+                commits
+                    .iter()
+                    .filter(|commit| commit.changed_packages.contains(&krate.name))
+                    .cloned()
+                    .collect()
+            }
+            // Without the commit it came from we can't tell the crate is up to date, so it gets queued
+            (None, Some(version)) => {
+                found_release = false;
+                diagnostics.record(
+                    format!("crate {}", krate.name),
+                    DoxieError::UnknownRelease {
+                        krate: krate.name.clone(),
+                        version: version.to_string(),
+                    },
+                );
+                vec![]
+            }
+            (None, None) => vec![],
+        };
+
+        crates.push(CrateReleaseStatus {
+            name: krate.name.clone(),
+            local_version: krate.version.clone(),
+            needs_release: published.is_none() || !found_release || !commits.is_empty(),
+            published_version: published.map(|version| version.to_string()),
+            commits,
+        });
+    }
+
+    cache.save()?;
+
+    crates.sort_by(|a, b| {
+        b.needs_release
+            .cmp(&a.needs_release)
+            .then(a.name.cmp(&b.name))
+    });
+
+    let queue = ReleaseQueue {
+        crates,
+        diagnostics: diagnostics.into_entries(),
+    };
+
+    write_blob(out_dir, "release_queue.json", &queue)
+}
+
+/// Find the commit a version of a crate was published from
+///
+/// Releases are usually tagged, but crates sometimes get published on their own. In that case we walk
+/// back from HEAD and pick the oldest commit where the manifest still had the published version.
+fn published_commit(
+    repo: &Repository,
+    tag_index: &TagIndex,
+    head: Oid,
+    krate: &WorkspaceCrate,
+    version: &semver::Version,
+) -> Option<Oid> {
+    if let Some(tag) = tag_index.get(version) {
+        return Some(tag.id);
+    }
+
+    let mut revwalk = repo.revwalk().ok()?;
+    revwalk.push(head).ok()?;
+    _ = revwalk.simplify_first_parent();
+
+    let version = version.to_string();
+    let mut workspaces = WorkspaceCache::default();
+    let mut found = None;
+
+    for id in revwalk.flatten() {
+        let tree = repo.find_commit(id).ok()?.tree().ok()?;
+
+        let local = workspaces.get(repo, &tree).and_then(|workspace| {
+            workspace
+                .crates
+                .iter()
+                .find(|other| other.name == krate.name)
+                .and_then(|other| other.version.clone())
+        });
+
+        match local.as_deref() == Some(version.as_str()) {
+            true => found = Some(id),
+            // Keep going until we find the version, then stop once it changes again
+            false if found.is_some() => break,
+            false => {}
+        }
+    }
+
+    found
+}
+
+#[test]
+fn queues_crates_changed_since_their_release() {
+    let fixture = crate::fixtures::dioxus_like();
+    let out_dir = tempfile::tempdir().unwrap();
+
+    // dioxus-core is up to date, dioxus-html was last published before #102 and dioxus-web never was
+    let index = tempfile::tempdir().unwrap();
+    for (path, name, version) in [
+        ("di/ox/dioxus-core", "dioxus-core", "0.6.0-alpha.0"),
+        ("di/ox/dioxus-html", "dioxus-html", "0.4.1"),
+    ] {
+        let path = index.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, format!(r#"{{"name":"{name}","vers":"{version}"}}"#)).unwrap();
+    }
+
+    collect_release_queue(
+        &fixture.repo,
+        &DoxieConfig::default(),
+        index.path(),
+        out_dir.path(),
+        Diagnostics::new(false),
+    )
+    .unwrap();
+
+    let queue: ReleaseQueue = serde_json::from_value(crate::fixtures::read_blob(
+        out_dir.path().join("release_queue.json"),
+    ))
+    .unwrap();
+
+    let status = |name: &str| {
+        let krate = queue
+            .crates
+            .iter()
+            .find(|krate| krate.name == name)
+            .unwrap();
+        let ids: Vec<_> = krate
+            .commits
+            .iter()
+            .map(|commit| commit.summary.as_str())
+            .collect();
+        (krate.needs_release, ids)
+    };
+
+    assert_eq!(status("dioxus-core"), (false, vec![]));
+    assert_eq!(
+        status("dioxus-html"),
+        (
+            true,
+            vec!["Merge pull request #102 from ealmloff/onmounted"]
+        )
+    );
+    assert_eq!(status("dioxus-web"), (true, vec![]));
+    assert_eq!(queue.crates[0].name, "dioxus-html");
+    assert!(queue.diagnostics.is_empty());
+
+    // A version that was never tagged and that no commit on main had can't be traced back
+    std::fs::write(
+        index.path().join("di/ox/dioxus-core"),
+        r#"{"name":"dioxus-core","vers":"0.3.9"}"#,
+    )
+    .unwrap();
+
+    collect_release_queue(
+        &fixture.repo,
+        &DoxieConfig::default(),
+        index.path(),
+        out_dir.path(),
+        Diagnostics::new(false),
+    )
+    .unwrap();
+
+    let queue: ReleaseQueue = serde_json::from_value(crate::fixtures::read_blob(
+        out_dir.path().join("release_queue.json"),
+    ))
+    .unwrap();
+
+    let core = queue
+        .crates
+        .iter()
+        .find(|krate| krate.name == "dioxus-core")
+        .unwrap();
+    assert!(core.needs_release);
+    assert_eq!(queue.diagnostics[0].scope, "crate dioxus-core");
+}
@@ -0,0 +1,262 @@
+//! Discover the release lines of a repo from its tags
+//!
+//! Rather than hardcoding which minor versions we care about, we parse every tag that matches the
+//! configured tag pattern as semver and group them by `major.minor`. When a new line ships, it shows
+//! up here without any code changes.
+
+use std::collections::BTreeMap;
+
+use doxie_types::ReleaseConfig;
+use git2::{Oid, Repository};
+use semver::Version;
+
+use crate::{commit_cache::CommitCache, error::Result};
+
+/// A tag that points at a release
+#[derive(Debug, Clone)]
+pub struct ReleaseTag {
+    /// The full name of the tag, ie `v0.5.1`
+    pub name: String,
+
+    pub version: Version,
+
+    /// The commit the tag points to, peeled through annotated tags
+    pub id: Oid,
+}
+
+/// A `major.minor` pair that identifies a release line
+pub type ReleaseLine = (u64, u64);
+
+/// Every release tag of a repo, built once and then queried
+///
+/// Tags are matched against the configured pattern exactly, so `v0.5.1` never matches `v0.5.11` or
+/// `dioxus-v0.5.1`. Annotated and lightweight tags are both peeled to the commit they point at.
+#[derive(Debug, Default)]
+pub struct TagIndex {
+    /// Every release grouped by release line and sorted by version
+    lines: BTreeMap<ReleaseLine, Vec<ReleaseTag>>,
+
+    /// Versions that more than one tag resolves to, ie `v0.5.1` and `v0.5.1+hotfix`
+    ambiguous: Vec<(Version, Vec<String>)>,
+}
+
+impl TagIndex {
+    /// Collect every release tag of the repo
+    pub fn new(repo: &Repository, config: &ReleaseConfig, cache: &mut CommitCache) -> Result<Self> {
+        let names = repo.tag_names(None)?;
+
+        let peeled = names
+            .iter()
+            .flatten()
+            .filter(|name| parse_tag(config, name).is_some())
+            .filter_map(|name| Some((name.to_string(), cache.peel_tag(repo, name)?)))
+            .collect::<Vec<_>>();
+
+        Ok(Self::from_peeled(config, peeled))
+    }
+
+    /// Build the index from tags that were already peeled to their commits
+    ///
+    /// Prereleases belong to the line they're a prerelease of, so `v0.6.0-alpha.0` is the first tag of
+    /// the `0.6` line and sorts before `v0.6.0`.
+    pub fn from_peeled(
+        config: &ReleaseConfig,
+        tags: impl IntoIterator<Item = (String, Oid)>,
+    ) -> Self {
+        // Build metadata doesn't count towards the version, so group by the version without it
+        let mut by_version: BTreeMap<Version, Vec<ReleaseTag>> = BTreeMap::new();
+
+        for (name, id) in tags {
+            let Some(version) = parse_tag(config, &name) else {
+                continue;
+            };
+
+            let mut key = version.clone();
+            key.build = semver::BuildMetadata::EMPTY;
+
+            by_version
+                .entry(key)
+                .or_default()
+                .push(ReleaseTag { name, version, id });
+        }
+
+        let mut index = Self::default();
+
+        for (version, mut tags) in by_version {
+            // Prefer the plain tag, then the shortest name, so the pick doesn't depend on tag order
+            tags.sort_by(|a, b| {
+                (!a.version.build.is_empty(), a.name.len(), &a.name).cmp(&(
+                    !b.version.build.is_empty(),
+                    b.name.len(),
+                    &b.name,
+                ))
+            });
+
+            if tags.len() > 1 {
+                let names: Vec<String> = tags.iter().map(|tag| tag.name.clone()).collect();
+                index.ambiguous.push((version.clone(), names));
+            }
+
+            let tag = tags.swap_remove(0);
+            index
+                .lines
+                .entry((version.major, version.minor))
+                .or_default()
+                .push(tag);
+        }
+
+        index
+    }
+
+    /// Every release grouped by release line and sorted by version
+    pub fn lines(&self) -> &BTreeMap<ReleaseLine, Vec<ReleaseTag>> {
+        &self.lines
+    }
+
+    /// All the releases of a single line, oldest first
+    pub fn releases_in_line(&self, line: ReleaseLine) -> &[ReleaseTag] {
+        self.lines.get(&line).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// The release of an exact version, ignoring build metadata
+    pub fn get(&self, version: &Version) -> Option<&ReleaseTag> {
+        self.releases_in_line((version.major, version.minor))
+            .iter()
+            .find(|tag| tag.version.cmp_precedence(version).is_eq())
+    }
+
+    /// The newest release before a version, across every line and including prereleases
+    pub fn previous_release(&self, version: &Version) -> Option<&ReleaseTag> {
+        self.lines
+            .values()
+            .flatten()
+            .filter(|tag| tag.version.cmp_precedence(version).is_lt())
+            .max_by(|a, b| a.version.cmp_precedence(&b.version))
+    }
+
+    /// The newest release that isn't a prerelease
+    pub fn newest_stable(&self) -> Option<&ReleaseTag> {
+        self.lines
+            .values()
+            .flatten()
+            .filter(|tag| tag.pre().is_none())
+            .max_by(|a, b| a.version.cmp_precedence(&b.version))
+    }
+
+    /// The versions that more than one tag resolves to, along with the names of those tags
+    ///
+    /// The first name is the tag the index uses for that version
+    pub fn ambiguous(&self) -> &[(Version, Vec<String>)] {
+        &self.ambiguous
+    }
+}
+
+impl ReleaseTag {
+    /// The prerelease identifier of this tag, ie `alpha.1` for `v0.6.0-alpha.1`
+    pub fn pre(&self) -> Option<String> {
+        match self.version.pre.is_empty() {
+            true => None,
+            false => Some(self.version.pre.to_string()),
+        }
+    }
+}
+
+/// Check if the config wants us to report on this release line
+///
+/// Lines that aren't reported on still exist - a newer line ends the previous one regardless of
+/// whether we report on it.
+pub fn is_reported(config: &ReleaseConfig, (major, minor): ReleaseLine) -> bool {
+    let line = (major as usize, minor as usize);
+
+    let wanted = config.parsed_lines();
+    let wanted = wanted.is_empty() || wanted.contains(&line);
+    let new_enough = config
+        .parsed_oldest_line()
+        .is_none_or(|oldest| line >= oldest);
+
+    wanted && new_enough
+}
+
+/// Parse the version out of a tag name using the configured pattern
+///
+/// Tags that don't match the pattern or aren't valid semver return `None`
+pub fn parse_tag(config: &ReleaseConfig, name: &str) -> Option<Version> {
+    let (prefix, suffix) = config
+        .tag_pattern
+        .split_once("{version}")
+        .unwrap_or((config.tag_pattern.as_str(), ""));
+
+    let version = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+
+    Version::parse(version).ok()
+}
+
+#[test]
+fn parses_tags_with_prefixes() {
+    let mut config = ReleaseConfig::default();
+
+    assert_eq!(parse_tag(&config, "v0.5.1"), Some(Version::new(0, 5, 1)));
+    assert_eq!(parse_tag(&config, "v1.0.0"), Some(Version::new(1, 0, 0)));
+    assert_eq!(
+        parse_tag(&config, "v0.6.0-alpha.1"),
+        Some(Version::parse("0.6.0-alpha.1").unwrap())
+    );
+    assert_eq!(parse_tag(&config, "dioxus-v0.5.1"), None);
+    assert_eq!(parse_tag(&config, "v0.5"), None);
+
+    config.tag_pattern = "dioxus-v{version}".to_string();
+    assert_eq!(
+        parse_tag(&config, "dioxus-v0.5.1"),
+        Some(Version::new(0, 5, 1))
+    );
+    assert_eq!(parse_tag(&config, "v0.5.1"), None);
+}
+
+#[test]
+fn indexes_release_tags() {
+    let config = ReleaseConfig::default();
+    let id = |n: u8| Oid::from_bytes(&[n; 20]).unwrap();
+
+    let index = TagIndex::from_peeled(
+        &config,
+        [
+            ("v0.4.3".to_string(), id(1)),
+            ("v0.5.0".to_string(), id(2)),
+            ("v0.5.1+hotfix".to_string(), id(3)),
+            ("v0.5.1".to_string(), id(4)),
+            ("v0.5.11".to_string(), id(5)),
+            ("v0.6.0-alpha.0".to_string(), id(6)),
+            ("dioxus-v0.5.1".to_string(), id(7)),
+            ("nightly".to_string(), id(8)),
+        ],
+    );
+
+    let names = |tags: &[ReleaseTag]| tags.iter().map(|tag| tag.name.clone()).collect::<Vec<_>>();
+
+    assert_eq!(
+        names(index.releases_in_line((0, 5))),
+        vec!["v0.5.0", "v0.5.1", "v0.5.11"]
+    );
+    assert!(index.releases_in_line((0, 7)).is_empty());
+
+    assert_eq!(index.get(&Version::new(0, 5, 1)).unwrap().id, id(4));
+    assert_eq!(
+        index.ambiguous(),
+        &[(
+            Version::new(0, 5, 1),
+            vec!["v0.5.1".to_string(), "v0.5.1+hotfix".to_string()]
+        )]
+    );
+
+    let previous = |version: &str| {
+        index
+            .previous_release(&Version::parse(version).unwrap())
+            .map(|tag| tag.name.as_str())
+    };
+    assert_eq!(previous("0.5.0"), Some("v0.4.3"));
+    assert_eq!(previous("0.6.0-alpha.0"), Some("v0.5.11"));
+    assert_eq!(previous("0.6.0"), Some("v0.6.0-alpha.0"));
+    assert_eq!(previous("0.4.3"), None);
+
+    assert_eq!(index.newest_stable().unwrap().name, "v0.5.11");
+}
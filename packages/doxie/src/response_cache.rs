@@ -0,0 +1,226 @@
+//! Remember github's answers between runs so we can ask "has this changed?" instead of downloading it again
+//!
+//! github sends an `ETag` and usually a `Last-Modified` header with every response. Sending them back
+//! as `If-None-Match` and `If-Modified-Since` gets an empty `304 Not Modified` if nothing changed, which
+//! doesn't count against the rate limit. We keep those validators and the body of every response in a
+//! json file in the output dir, keyed by url, so the next run can make all its reads conditional.
+//!
+//! Every subcommand shares the output dir, so saving merges with whatever is on disk instead of
+//! replacing it - `doxie comment` shouldn't throw away what `doxie commits` learned. Responses nobody
+//! asked for in a month drop out, which takes care of closed PRs and old pages.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use http::{
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    HeaderMap, HeaderValue,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{DoxieError, Result},
+    rate_limit,
+};
+
+/// Bump this whenever the meaning of a cached field changes so old caches get thrown away
+const CACHE_VERSION: u32 = 2;
+
+const CACHE_FILE: &str = "github_cache.json";
+
+/// How long a response is kept after the last run that asked for it, in seconds
+const MAX_AGE: u64 = 30 * 24 * 60 * 60;
+
+/// A response we can send validators for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+
+    /// The url of the page after this one, if it's a page of a listing
+    pub next: Option<String>,
+}
+
+impl CachedResponse {
+    /// Keep the validators of a response, `None` if github didn't send any
+    pub fn new(headers: &HeaderMap, body: String, next: Option<String>) -> Option<Self> {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+        if etag.is_none() && last_modified.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            etag,
+            last_modified,
+            body,
+            next,
+        })
+    }
+
+    /// The headers that make a request only return something if it changed since this response
+    pub fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        let validators = [
+            (IF_NONE_MATCH, &self.etag),
+            (IF_MODIFIED_SINCE, &self.last_modified),
+        ];
+        for (name, value) in validators {
+            if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+                headers.insert(name, value);
+            }
+        }
+
+        headers
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ResponseCache {
+    version: u32,
+
+    responses: HashMap<String, CachedResponse>,
+
+    /// When each url was last asked for, in seconds since the unix epoch
+    used_at: HashMap<String, u64>,
+
+    /// Where to save the cache, `None` if it only lives for this run
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl ResponseCache {
+    /// Load the cache from the output dir, starting fresh if it's missing, stale or unreadable
+    pub fn load(out_dir: &Path) -> Self {
+        let path = out_dir.join(CACHE_FILE);
+
+        let mut cache = Self::read(&path);
+        cache.path = Some(path);
+        cache
+    }
+
+    /// The cache saved at `path`, or an empty one if it's missing, stale or unreadable
+    fn read(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|raw| serde_json::from_str::<ResponseCache>(&raw).ok())
+            .filter(|cache| cache.version == CACHE_VERSION)
+            .unwrap_or_else(|| Self {
+                version: CACHE_VERSION,
+                ..Default::default()
+            })
+    }
+
+    pub fn get(&mut self, url: &str) -> Option<&CachedResponse> {
+        self.used_at.insert(url.to_string(), rate_limit::now());
+        self.responses.get(url)
+    }
+
+    pub fn insert(&mut self, url: &str, response: CachedResponse) {
+        self.used_at.insert(url.to_string(), rate_limit::now());
+        self.responses.insert(url.to_string(), response);
+    }
+
+    /// Merge the responses into the cache on disk and write it back, dropping the ones nobody has asked
+    /// for in a while
+    ///
+    /// Another subcommand might have saved since we loaded, so for every url the most recently used
+    /// response wins
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut merged = Self::read(path);
+        for (url, response) in self.responses.iter() {
+            let ours = self.used_at.get(url).copied().unwrap_or_default();
+            let theirs = merged.used_at.get(url).copied().unwrap_or_default();
+
+            if ours >= theirs || !merged.responses.contains_key(url) {
+                merged.responses.insert(url.clone(), response.clone());
+                merged.used_at.insert(url.clone(), ours.max(theirs));
+            }
+        }
+
+        merged.expire(rate_limit::now());
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(DoxieError::io("create", dir))?;
+        }
+
+        let raw = serde_json::to_string(&merged)?;
+        std::fs::write(path, raw).map_err(DoxieError::io("write", path))
+    }
+
+    /// Drop the responses that weren't asked for in the last [`MAX_AGE`] seconds
+    fn expire(&mut self, now: u64) {
+        let used_at = &self.used_at;
+        self.responses.retain(|url, _| {
+            used_at
+                .get(url)
+                .is_some_and(|used| now.saturating_sub(*used) < MAX_AGE)
+        });
+
+        let responses = &self.responses;
+        self.used_at.retain(|url, _| responses.contains_key(url));
+    }
+}
+
+#[test]
+fn merges_with_the_cache_on_disk() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(ETAG, HeaderValue::from_static("W/\"abc\""));
+    let prs = CachedResponse::new(&headers, "[]".to_string(), None).unwrap();
+
+    // Responses without validators can't be asked about, so there's no point keeping them
+    assert_eq!(
+        CachedResponse::new(&HeaderMap::new(), "[]".to_string(), None),
+        None
+    );
+
+    let conditional = prs.conditional_headers();
+    assert_eq!(conditional[IF_NONE_MATCH], "W/\"abc\"");
+    assert!(!conditional.contains_key(IF_MODIFIED_SINCE));
+
+    // Two subcommands load the cache before either saves
+    let mut commits = ResponseCache::load(dir.path());
+    let mut comment = ResponseCache::load(dir.path());
+
+    commits.insert("/repos/dioxuslabs/dioxus/issues/101/timeline", prs.clone());
+    commits.save().unwrap();
+
+    comment.insert("/repos/dioxuslabs/dioxus/pulls/2365", prs.clone());
+    comment.save().unwrap();
+
+    // Neither run threw away what the other one saved
+    let mut cache = ResponseCache::load(dir.path());
+    assert_eq!(
+        cache.get("/repos/dioxuslabs/dioxus/issues/101/timeline"),
+        Some(&prs)
+    );
+    assert_eq!(cache.get("/repos/dioxuslabs/dioxus/pulls/2365"), Some(&prs));
+
+    // Responses nobody asked for in a while are dropped
+    let now = rate_limit::now();
+    cache.used_at.insert(
+        "/repos/dioxuslabs/dioxus/pulls/2365".to_string(),
+        now - MAX_AGE,
+    );
+    cache.expire(now);
+    assert_eq!(cache.get("/repos/dioxuslabs/dioxus/pulls/2365"), None);
+    assert!(cache
+        .get("/repos/dioxuslabs/dioxus/issues/101/timeline")
+        .is_some());
+}
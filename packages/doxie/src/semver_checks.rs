@@ -0,0 +1,369 @@
+//! Run `cargo semver-checks` against the crates a change touches
+//!
+//! The checks need a real checkout of the commit, so we spin up a throwaway git worktree for it and run
+//! the checks there against a baseline revision. The human-readable report gets parsed into
+//! `SemverFinding`s so the comment and the UI can point at exactly what broke.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use doxie_types::*;
+use git2::{Oid, Repository};
+use tempfile::TempDir;
+use tokio::process::Command;
+
+use crate::{
+    error::{Diagnostics, DoxieError},
+    workspace,
+};
+
+/// Check if the changes to the given crates at `commit` are semver compatible with `baseline`
+///
+/// Returns `None` if there are no crates to check, `cargo semver-checks` isn't installed or the worktree
+/// couldn't be created
+pub async fn is_compatible(
+    repo_path: &Path,
+    commit: &str,
+    baseline: &str,
+    crates: &[String],
+) -> Option<bool> {
+    // A commit that doesn't touch any crate hasn't been shown to be compatible with anything
+    if crates.is_empty() {
+        return None;
+    }
+
+    let findings = check(repo_path, commit, baseline, crates).await?;
+
+    Some(
+        !findings
+            .iter()
+            .any(|finding| finding.severity == SemverSeverity::Failure),
+    )
+}
+
+/// Run the checks for the given crates at `commit` against `baseline` and collect everything they found
+///
+/// Returns `None` if `cargo semver-checks` isn't installed or the worktree couldn't be created
+pub async fn check(
+    repo_path: &Path,
+    commit: &str,
+    baseline: &str,
+    crates: &[String],
+) -> Option<Vec<SemverFinding>> {
+    if !is_installed().await {
+        return None;
+    }
+
+    let worktree = Worktree::new(repo_path, commit).await?;
+
+    let mut findings = vec![];
+
+    for krate in crates {
+        let output = Command::new("cargo")
+            .args(["semver-checks", "check-release", "--baseline-rev", baseline])
+            .args(["--package", krate, "--color", "never"])
+            .current_dir(&worktree.path)
+            .output()
+            .await
+            .ok()?;
+
+        // The report is split between stdout and stderr depending on the version of the tool
+        let report = format!(
+            "{}\n{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let found = parse_findings(krate, &report);
+
+        // Usually this means the crate doesn't exist at the baseline or doesn't build
+        if !output.status.success() && found.is_empty() {
+            eprintln!("cargo semver-checks failed for {krate} at {commit}:\n{report}");
+        }
+
+        findings.extend(found);
+    }
+
+    Some(findings)
+}
+
+/// Check every commit of an unreleased version against the last release of its line
+///
+/// Each commit is compared against the release tag, so a break shows up on every commit after the one
+/// that introduced it. We only keep it on the oldest one so it's pinned on the PR that caused it.
+///
+/// A commit that can't be checked is recorded in the diagnostics and the rest are still checked.
+pub async fn check_unreleased(
+    repo_path: &Path,
+    baseline: &str,
+    commits: &mut [PrCommit],
+    diagnostics: &mut Diagnostics,
+) {
+    // Not having the tool isn't a problem with any one commit
+    if !is_installed().await {
+        return;
+    }
+
+    let mut seen = HashSet::new();
+
+    // The commits come newest first
+    for commit in commits.iter_mut().rev() {
+        let crates: Vec<String> = commit.changed_packages.iter().cloned().collect();
+        if crates.is_empty() {
+            continue;
+        }
+
+        let Some(findings) = check(repo_path, &commit.commit_hash, baseline, &crates).await else {
+            diagnostics.record(
+                format!("semver checks of commit {}", commit.commit_hash),
+                DoxieError::Command {
+                    command: "cargo semver-checks".to_string(),
+                    reason: format!("couldn't check it against {baseline}"),
+                },
+            );
+            continue;
+        };
+
+        commit.semver_findings = findings
+            .into_iter()
+            .filter(|finding| seen.insert(finding.clone()))
+            .collect();
+    }
+}
+
+/// Check the changes of an open PR against the commit it branched off of
+///
+/// Main collects breaking changes on purpose between releases, so comparing against the release tag would
+/// blame every PR for whatever landed before it. The branch point isolates what the PR itself changes.
+pub async fn check_pr(
+    repo_path: &Path,
+    main_repo: &str,
+    number: u64,
+    base: &str,
+) -> Option<Vec<SemverFinding>> {
+    // The PR might come from a fork, so grab its head from the main repo instead of a remote
+    // It goes into a ref of our own so we don't clobber the `FETCH_HEAD` of whoever owns the checkout
+    let local_ref = format!("refs/doxie/pr/{number}");
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(["fetch", "--quiet"])
+        .arg(format!("https://github.com/{main_repo}.git"))
+        .arg(format!("+refs/pull/{number}/head:{local_ref}"))
+        .status()
+        .await
+        .ok()?;
+
+    if !status.success() {
+        eprintln!("Failed to fetch the head of #{number}");
+        return None;
+    }
+
+    let (head, fork_point, crates) = {
+        let repo = Repository::open(repo_path).ok()?;
+        let head = repo.refname_to_id(&local_ref).ok()?;
+        let fork_point = repo.merge_base(Oid::from_str(base).ok()?, head).ok()?;
+
+        let old = repo.find_commit(fork_point).ok()?.tree().ok()?;
+        let new = repo.find_commit(head).ok()?.tree().ok()?;
+        let crates = workspace::changed_crates(&repo, &old, &new);
+
+        (head.to_string(), fork_point.to_string(), crates)
+    };
+
+    let mut crates: Vec<String> = crates.into_iter().collect();
+    crates.sort();
+
+    check(repo_path, &head, &fork_point, &crates).await
+}
+
+async fn is_installed() -> bool {
+    Command::new("cargo")
+        .args(["semver-checks", "--version"])
+        .output()
+        .await
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Parse the report of `cargo semver-checks` into findings
+///
+/// Every lint starts with a header like `--- failure function_missing: pub fn removed or renamed ---`,
+/// and lists the items it found under `Failed in:`. A lint without any items still gets a single finding.
+fn parse_findings(krate: &str, report: &str) -> Vec<SemverFinding> {
+    let mut findings = vec![];
+    let mut lines = report.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let Some(header) = line
+            .trim()
+            .strip_prefix("--- ")
+            .and_then(|header| header.strip_suffix(" ---"))
+        else {
+            continue;
+        };
+
+        let Some((kind, rest)) = header.split_once(' ') else {
+            continue;
+        };
+
+        let severity = match kind {
+            "failure" => SemverSeverity::Failure,
+            "warning" => SemverSeverity::Warning,
+            _ => continue,
+        };
+
+        let (lint, description) = rest.split_once(':').unwrap_or((rest, ""));
+
+        let finding = |item: Option<String>| SemverFinding {
+            krate: krate.to_string(),
+            lint: lint.trim().to_string(),
+            severity,
+            item,
+            description: description.trim().to_string(),
+        };
+
+        // Skip ahead to the items, stopping if we run into the next lint
+        let mut items = vec![];
+        while let Some(line) = lines.peek() {
+            if line.trim().starts_with("--- ") {
+                break;
+            }
+
+            let line = lines.next().unwrap_or_default();
+            if line.trim() != "Failed in:" {
+                continue;
+            }
+
+            while let Some(item) = lines.peek().filter(|item| !item.trim().is_empty()) {
+                items.push(item_name(item));
+                lines.next();
+            }
+        }
+
+        match items.is_empty() {
+            true => findings.push(finding(None)),
+            false => findings.extend(items.into_iter().map(|item| finding(Some(item)))),
+        }
+    }
+
+    findings
+}
+
+/// Strip the location off an item, ie `function foo::bar, previously in file src/lib.rs:10`
+fn item_name(line: &str) -> String {
+    let line = line.trim();
+
+    [", previously in file ", " in file ", " in "]
+        .iter()
+        .find_map(|separator| line.split_once(separator))
+        .map(|(item, _)| item)
+        .unwrap_or(line)
+        .to_string()
+}
+
+/// A detached git worktree that gets cleaned up when dropped
+///
+/// Every worktree gets a fresh temp dir, so checking out the same commit twice or running alongside
+/// another doxie never touches someone else's checkout
+pub struct Worktree {
+    repo_path: PathBuf,
+    pub path: PathBuf,
+
+    /// Removed after the worktree is, since fields drop after `Drop::drop`
+    _dir: TempDir,
+}
+
+impl Worktree {
+    pub async fn new(repo_path: &Path, commit: &str) -> Option<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("doxie-worktree-")
+            .tempdir()
+            .ok()?;
+        let path = dir.path().join("checkout");
+
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(repo_path)
+            .args(["worktree", "add", "--detach"])
+            .arg(&path)
+            .arg(commit)
+            .status()
+            .await
+            .ok()?;
+
+        status.success().then(|| Self {
+            repo_path: repo_path.to_path_buf(),
+            path,
+            _dir: dir,
+        })
+    }
+}
+
+impl Drop for Worktree {
+    fn drop(&mut self) {
+        _ = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&self.repo_path)
+            .args(["worktree", "remove", "--force"])
+            .arg(&self.path)
+            .status();
+    }
+}
+
+#[test]
+fn parses_semver_checks_report() {
+    let report = r#"
+     Parsing dioxus-core v0.5.1 (current)
+     Checking dioxus-core v0.5.0 -> v0.5.1 (minor change)
+     Checked [   0.021s] 87 checks: 85 pass, 2 fail, 0 warn, 0 skip
+
+--- failure function_missing: pub fn removed or renamed ---
+
+Description:
+A publicly-visible function cannot be imported by its prior path.
+        ref: https://doc.rust-lang.org/cargo/reference/semver.html#item-remove
+       impl: https://github.com/obi1kenobi/cargo-semver-checks/tree/v0.31.0/src/lints/function_missing.ron
+
+Failed in:
+  function dioxus_core::prelude::use_hook_with_cleanup, previously in file /tmp/dioxus/packages/core/src/hooks.rs:82
+  function dioxus_core::prelude::schedule_update, previously in file /tmp/dioxus/packages/core/src/global_context.rs:113
+
+--- warning function_must_use_added: function #[must_use] added ---
+
+Description:
+A function is now #[must_use].
+
+Failed in:
+  function dioxus_core::prelude::current_scope_id in /tmp/dioxus/packages/core/src/global_context.rs:10
+
+     Summary semver requires new major version: 1 major and 0 minor checks failed
+"#;
+
+    let findings = parse_findings("dioxus-core", report);
+
+    assert_eq!(findings.len(), 3);
+    assert_eq!(findings[0].lint, "function_missing");
+    assert_eq!(findings[0].severity, SemverSeverity::Failure);
+    assert_eq!(findings[0].description, "pub fn removed or renamed");
+    assert_eq!(
+        findings[0].item.as_deref(),
+        Some("function dioxus_core::prelude::use_hook_with_cleanup")
+    );
+    assert_eq!(
+        findings[1].item.as_deref(),
+        Some("function dioxus_core::prelude::schedule_update")
+    );
+    assert_eq!(findings[2].severity, SemverSeverity::Warning);
+    assert_eq!(
+        findings[2].item.as_deref(),
+        Some("function dioxus_core::prelude::current_scope_id")
+    );
+    assert!(findings
+        .iter()
+        .all(|finding| finding.krate == "dioxus-core"));
+
+    assert!(parse_findings("dioxus-core", "     Summary no semver update required").is_empty());
+}
@@ -0,0 +1,358 @@
+//! Measure how big the wasm of the examples comes out
+//!
+//! Every configured example gets built for `wasm32-unknown-unknown` at each opt-level, and then run
+//! through `wasm-opt` at the same level, since that's what `dx` does for a release build. For each of
+//! those we record the raw size along with the gzip and brotli sizes, which are closer to what a user
+//! actually downloads. An example that fails to build or optimize gets a diagnostic and the rest carry on.
+//!
+//! The builds straight out of cargo still have their function names, so those also get broken down by
+//! crate and symbol with [`wasm_symbols`], and [`render_diff`] shows how that changed between two commits.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use doxie_types::*;
+use tokio::process::Command;
+
+use crate::{
+    error::{Diagnostics, DoxieError, Result},
+    wasm_symbols,
+};
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+/// Build every example every way and measure it, keyed by [`CompileSizeStats::name`]
+pub async fn collect(
+    repo_path: &Path,
+    config: &SizeConfig,
+    diagnostics: &mut Diagnostics,
+) -> HashMap<String, CompileSizeStats> {
+    let mut sizes = HashMap::new();
+
+    for example in &config.examples {
+        for opt_level in &config.opt_levels {
+            let scope = format!("size of {example} at opt-level {opt_level}");
+
+            let wasm = match build(repo_path, config, example, opt_level).await {
+                Ok(wasm) => wasm,
+                Err(err) => {
+                    diagnostics.record(scope, err);
+                    continue;
+                }
+            };
+
+            let builds = [
+                (false, Ok(wasm.clone())),
+                (true, wasm_opt(&wasm, opt_level).await),
+            ];
+
+            for (optimized, wasm) in builds {
+                let stats = wasm.and_then(|wasm| {
+                    measure(&wasm, example, opt_level, optimized, config.top_symbols)
+                });
+
+                match stats {
+                    Ok(stats) => {
+                        sizes.insert(stats.name.clone(), stats);
+                    }
+                    Err(err) if optimized => {
+                        diagnostics.record(format!("{scope} with wasm-opt"), err)
+                    }
+                    Err(err) => diagnostics.record(&scope, err),
+                }
+            }
+        }
+    }
+
+    sizes
+}
+
+/// Build an example in release mode at an opt-level and hand back the path of its wasm
+async fn build(
+    repo_path: &Path,
+    config: &SizeConfig,
+    example: &str,
+    opt_level: &str,
+) -> Result<PathBuf> {
+    let target_dir = repo_path
+        .join(&config.target_dir)
+        .join(format!("opt-{opt_level}"));
+
+    let status = Command::new("cargo")
+        .args([
+            "build",
+            "--release",
+            "--target",
+            TARGET,
+            "--example",
+            example,
+        ])
+        .args(&config.build_args)
+        .env("CARGO_PROFILE_RELEASE_OPT_LEVEL", opt_level)
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .current_dir(repo_path)
+        .status()
+        .await;
+
+    let failed = |reason: String| DoxieError::Command {
+        command: format!("cargo build --example {example}"),
+        reason,
+    };
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => return Err(failed(status.to_string())),
+        Err(err) => return Err(failed(err.to_string())),
+    }
+
+    // Dashes in the example's name turn into underscores in the file name
+    Ok(target_dir
+        .join(TARGET)
+        .join("release")
+        .join("examples")
+        .join(format!("{}.wasm", example.replace('-', "_"))))
+}
+
+/// Run `wasm-opt` on a build at the same level, next to the original
+async fn wasm_opt(wasm: &Path, opt_level: &str) -> Result<PathBuf> {
+    let out = wasm.with_extension("opt.wasm");
+
+    let status = Command::new("wasm-opt")
+        .arg(format!("-O{opt_level}"))
+        .arg(wasm)
+        .arg("-o")
+        .arg(&out)
+        .status()
+        .await;
+
+    let failed = |reason: String| DoxieError::Command {
+        command: format!("wasm-opt -O{opt_level}"),
+        reason,
+    };
+    match status {
+        Ok(status) if status.success() => Ok(out),
+        Ok(status) => Err(failed(status.to_string())),
+        Err(err) => Err(failed(err.to_string())),
+    }
+}
+
+fn measure(
+    wasm: &Path,
+    example: &str,
+    opt_level: &str,
+    wasm_opt: bool,
+    top_symbols: usize,
+) -> Result<CompileSizeStats> {
+    let bytes = std::fs::read(wasm).map_err(DoxieError::io("read", wasm))?;
+    let (gzip_size, brotli_size) = compressed_sizes(&bytes);
+
+    // `wasm-opt` strips the names, so there's nothing to attribute the bytes to
+    let breakdown = match wasm_opt {
+        true => wasm_symbols::Breakdown::default(),
+        false => wasm_symbols::attribute(&bytes, top_symbols)?,
+    };
+
+    let name = match wasm_opt {
+        true => format!("{example}/{opt_level}+wasm-opt"),
+        false => format!("{example}/{opt_level}"),
+    };
+
+    Ok(CompileSizeStats {
+        name,
+        example: example.to_string(),
+        opt_level: opt_level.to_string(),
+        wasm_opt,
+        raw_size: bytes.len() as u64,
+        gzip_size,
+        brotli_size,
+        crates: breakdown.crates,
+        top_symbols: breakdown.top_symbols,
+    })
+}
+
+/// The size of the bytes compressed with gzip and brotli, both at their best compression
+fn compressed_sizes(bytes: &[u8]) -> (u64, u64) {
+    let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::best());
+    gzip.write_all(bytes).unwrap();
+    let gzip = gzip.finish().unwrap();
+
+    let mut brotli = vec![];
+    {
+        // Quality 11 with a 4MB window, which is what `brotli --best` uses
+        let mut writer = brotli::CompressorWriter::new(&mut brotli, 4096, 11, 22);
+        writer.write_all(bytes).unwrap();
+    }
+
+    (gzip.len() as u64, brotli.len() as u64)
+}
+
+/// A markdown table of how the size of every build changed between two commits, followed by the crates
+/// that grew or shrank the most in each build
+///
+/// `None` if the commits don't have any builds in common
+pub fn render_diff(base: &CommitStats, head: &CommitStats) -> Option<String> {
+    let mut builds: Vec<_> = head
+        .compile_size
+        .values()
+        .filter_map(|head| Some((base.compile_size.get(&head.name)?, head)))
+        .collect();
+    builds.sort_by(|(_, a), (_, b)| a.name.cmp(&b.name));
+
+    if builds.is_empty() {
+        return None;
+    }
+
+    let mut table = String::from(
+        "Brotli compressed sizes of the examples.\n\n| Build | Base | Head | Change |\n| --- | --- | --- | --- |\n",
+    );
+    for (base, head) in builds.iter() {
+        table.push_str(&format!(
+            "| `{}` | {} | {} | {} |\n",
+            head.name,
+            format_bytes(base.brotli_size),
+            format_bytes(head.brotli_size),
+            format_change(base.brotli_size, head.brotli_size),
+        ));
+    }
+
+    for (base, head) in builds.iter() {
+        let mut crates: Vec<_> = base
+            .crates
+            .keys()
+            .chain(head.crates.keys())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .map(|krate| {
+                let size = |stats: &CompileSizeStats| stats.crates.get(krate).copied().unwrap_or(0);
+                (krate, size(base), size(head))
+            })
+            .filter(|(_, base, head)| base != head)
+            .collect();
+
+        if crates.is_empty() {
+            continue;
+        }
+
+        crates.sort_by_key(|(_, base, head)| std::cmp::Reverse(head.abs_diff(*base)));
+        crates.truncate(10);
+
+        table.push_str(&format!(
+            "\n<details><summary>Crates that changed in <code>{}</code>, before compression</summary>\n\n| Crate | Base | Head | Change |\n| --- | --- | --- | --- |\n",
+            head.name
+        ));
+        for (krate, base, head) in crates {
+            // A crate that wasn't in the build before is exactly what we want to stand out
+            let before = match base {
+                0 => "new".to_string(),
+                base => format_bytes(base),
+            };
+
+            table.push_str(&format!(
+                "| `{krate}` | {before} | {} | {} |\n",
+                format_bytes(head),
+                format_change(base, head),
+            ));
+        }
+        table.push_str("\n</details>\n");
+    }
+
+    Some(table)
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes} B"),
+        _ => format!("{:.1} KiB", bytes as f64 / 1024.0),
+    }
+}
+
+fn format_change(base: u64, head: u64) -> String {
+    let delta = head as i64 - base as i64;
+    let sign = if delta < 0 { "-" } else { "+" };
+    let bytes = format_bytes(delta.unsigned_abs());
+
+    match base {
+        0 => format!("{sign}{bytes}"),
+        _ => format!(
+            "{sign}{bytes} ({:+.1}%)",
+            delta as f64 / base as f64 * 100.0
+        ),
+    }
+}
+
+#[test]
+fn measures_compressed_sizes() {
+    let dir = tempfile::tempdir().unwrap();
+    let wasm = dir.path().join("counter.wasm");
+
+    // The magic number and version of a wasm module, followed by something that compresses well
+    let mut bytes = b"\0asm\x01\0\0\0".to_vec();
+    bytes.extend(b"dioxus_core::diff ".repeat(1000));
+    std::fs::write(&wasm, &bytes).unwrap();
+
+    let stats = measure(&wasm, "counter", "z", true, 20).unwrap();
+    assert_eq!(stats.name, "counter/z+wasm-opt");
+    assert_eq!(stats.raw_size, bytes.len() as u64);
+    assert!(stats.gzip_size < stats.raw_size / 10);
+    assert!(stats.brotli_size < stats.gzip_size);
+
+    let missing = measure(&dir.path().join("todomvc.wasm"), "todomvc", "z", false, 20);
+    assert!(matches!(missing, Err(DoxieError::Io { .. })));
+}
+
+#[test]
+fn renders_the_size_diff_of_a_pr() {
+    let build = |name: &str, brotli_size: u64, crates: &[(&str, u64)]| CompileSizeStats {
+        name: name.to_string(),
+        brotli_size,
+        crates: crates
+            .iter()
+            .map(|(krate, size)| (krate.to_string(), *size))
+            .collect(),
+        ..Default::default()
+    };
+    let stats = |builds: Vec<CompileSizeStats>| CommitStats {
+        compile_size: builds
+            .into_iter()
+            .map(|build| (build.name.clone(), build))
+            .collect(),
+        ..Default::default()
+    };
+
+    let base = stats(vec![
+        build(
+            "counter/z",
+            100 * 1024,
+            &[("dioxus_core", 80_000), ("std", 20_000)],
+        ),
+        build("todomvc/z", 200 * 1024, &[]),
+    ]);
+    let head = stats(vec![
+        build(
+            "counter/z",
+            150 * 1024,
+            &[
+                ("dioxus_core", 80_000),
+                ("std", 21_000),
+                ("regex_automata", 40_000),
+            ],
+        ),
+        build("todomvc/z", 190 * 1024, &[]),
+        build("hello/z", 10, &[]),
+    ]);
+
+    let diff = render_diff(&base, &head).unwrap();
+    assert!(diff.contains("| `counter/z` | 100.0 KiB | 150.0 KiB | +50.0 KiB (+50.0%) |"));
+    assert!(diff.contains("| `todomvc/z` | 200.0 KiB | 190.0 KiB | -10.0 KiB (-5.0%) |"));
+    assert!(!diff.contains("hello/z"));
+
+    // The new crate comes first and unchanged ones are left out
+    let crates = diff.split("<code>counter/z</code>").nth(1).unwrap();
+    assert!(crates.contains("| `regex_automata` | new | 39.1 KiB | +39.1 KiB |\n| `std` |"));
+    assert!(!crates.contains("dioxus_core"));
+    assert!(!diff.contains("<code>todomvc/z</code>"));
+
+    assert_eq!(render_diff(&base, &CommitStats::default()), None);
+}
@@ -0,0 +1,195 @@
+//! Work out which crates the bytes of a wasm build come from
+//!
+//! Unless it gets stripped, a wasm module has a `name` custom section with the name of every function,
+//! which for rust code is the mangled path of the item. We demangle those, take the crate from the
+//! front of the path and add up the size of each function's body per crate. That's roughly what
+//! `twiggy top` reports, without having to install it on the runner.
+
+use std::collections::HashMap;
+
+use doxie_types::SymbolSize;
+use wasmparser::{KnownCustom, Name, Parser, Payload, TypeRef};
+
+use crate::error::Result;
+
+/// Where the bytes of a build went
+#[derive(Debug, Default)]
+pub struct Breakdown {
+    pub crates: HashMap<String, u64>,
+
+    /// The biggest functions, biggest first
+    pub top_symbols: Vec<SymbolSize>,
+}
+
+/// Attribute the code and data of a wasm module to crates, keeping the `top` biggest functions
+pub fn attribute(wasm: &[u8], top: usize) -> Result<Breakdown> {
+    let mut imported_functions = 0;
+    let mut bodies = vec![];
+    let mut names = HashMap::new();
+    let mut data = 0;
+
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload? {
+            // Imports share the index space of the functions, but don't have a body
+            Payload::ImportSection(imports) => {
+                for import in imports {
+                    if let TypeRef::Func(_) = import?.ty {
+                        imported_functions += 1;
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => bodies.push(body.range().len() as u64),
+            Payload::DataSection(segments) => {
+                for segment in segments {
+                    data += segment?.data.len() as u64;
+                }
+            }
+            Payload::CustomSection(section) => {
+                let KnownCustom::Name(subsections) = section.as_known() else {
+                    continue;
+                };
+
+                for subsection in subsections {
+                    let Name::Function(functions) = subsection? else {
+                        continue;
+                    };
+
+                    for naming in functions {
+                        let naming = naming?;
+                        names.insert(naming.index, naming.name);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut breakdown = Breakdown::default();
+    if data > 0 {
+        breakdown.crates.insert("[data]".to_string(), data);
+    }
+
+    let mut symbols = vec![];
+
+    for (i, size) in bodies.into_iter().enumerate() {
+        let index = imported_functions + i as u32;
+        let name = match names.get(&index) {
+            Some(name) => format!("{:#}", rustc_demangle::demangle(name)),
+            None => format!("function[{index}]"),
+        };
+        let krate = crate_of(&name);
+
+        *breakdown.crates.entry(krate.clone()).or_default() += size;
+        symbols.push(SymbolSize { name, krate, size });
+    }
+
+    symbols.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    symbols.truncate(top);
+    breakdown.top_symbols = symbols;
+
+    Ok(breakdown)
+}
+
+/// The crate at the front of a demangled path
+///
+/// Trait impls like `<dioxus_core::VNode as core::clone::Clone>::clone` count towards the crate of the
+/// type, since that's the crate that made the impl show up in the build
+fn crate_of(symbol: &str) -> String {
+    let path = symbol
+        .trim_start_matches(['<', '&', '['])
+        .trim_start_matches("mut ")
+        .trim_start_matches("*const ")
+        .trim_start_matches("*mut ");
+
+    let end = path
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(path.len());
+
+    match path[end..].starts_with("::") && end > 0 {
+        true => path[..end].to_string(),
+        false => "[other]".to_string(),
+    }
+}
+
+#[test]
+fn attributes_functions_to_crates() {
+    // A module with one function type, the given function bodies and a name section naming them
+    fn module(functions: &[(&str, usize)]) -> Vec<u8> {
+        // Lengths are unsigned LEB128, seven bits to a byte
+        let leb = |mut n: usize| {
+            let mut bytes = vec![];
+            loop {
+                let byte = (n & 0x7f) as u8;
+                n >>= 7;
+                match n {
+                    0 => break [bytes, vec![byte]].concat(),
+                    _ => bytes.push(byte | 0x80),
+                }
+            }
+        };
+        let section =
+            |id: u8, contents: Vec<u8>| [vec![id], leb(contents.len()), contents].concat();
+
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.extend(section(1, vec![1, 0x60, 0, 0]));
+
+        let mut types = vec![functions.len() as u8];
+        types.extend(vec![0; functions.len()]);
+        wasm.extend(section(3, types));
+
+        let mut code = vec![functions.len() as u8];
+        for &(_, nops) in functions {
+            // No locals, the nops, and then `end`
+            code.push(nops as u8 + 2);
+            code.push(0);
+            code.extend(vec![0x01; nops]);
+            code.push(0x0b);
+        }
+        wasm.extend(section(10, code));
+
+        let mut function_names = vec![functions.len() as u8];
+        for (index, &(name, _)) in functions.iter().enumerate() {
+            function_names.extend([index as u8, name.len() as u8]);
+            function_names.extend(name.as_bytes());
+        }
+        let mut names = b"\x04name".to_vec();
+        names.extend(section(1, function_names));
+        wasm.extend(section(0, names));
+
+        wasm
+    }
+
+    let wasm = module(&[
+        ("_ZN11dioxus_core4diff17h0123456789abcdefE", 40),
+        ("<dioxus_core::VNode as core::clone::Clone>::clone", 10),
+        ("regex_automata::meta::Regex::new", 60),
+        ("memcpy", 5),
+    ]);
+
+    let breakdown = attribute(&wasm, 2).unwrap();
+
+    assert_eq!(breakdown.crates["dioxus_core"], 42 + 12);
+    assert_eq!(breakdown.crates["regex_automata"], 62);
+    assert_eq!(breakdown.crates["[other]"], 7);
+    assert_eq!(
+        breakdown.top_symbols,
+        [
+            SymbolSize {
+                name: "regex_automata::meta::Regex::new".to_string(),
+                krate: "regex_automata".to_string(),
+                size: 62,
+            },
+            SymbolSize {
+                name: "dioxus_core::diff".to_string(),
+                krate: "dioxus_core".to_string(),
+                size: 42,
+            },
+        ]
+    );
+
+    assert_eq!(
+        crate_of("<&alloc::string::String as core::fmt::Display>::fmt"),
+        "alloc"
+    );
+    assert_eq!(crate_of("function[3]"), "[other]");
+}
@@ -0,0 +1,478 @@
+//! Read the cargo workspace straight out of a commit's tree
+//!
+//! Crates don't always live directly under `packages/` and their folder name isn't always their crate
+//! name, so we read the real `Cargo.toml` manifests at each commit and map changed files to the crate
+//! that owns them.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use git2::{ObjectType, Oid, Repository, Tree};
+
+/// A crate that's a member of the workspace
+#[derive(Debug, Clone)]
+pub struct WorkspaceCrate {
+    /// The name of the crate from its `[package]` table
+    pub name: String,
+
+    /// The folder of the crate, relative to the root of the repo
+    pub path: PathBuf,
+
+    /// The version from the manifest, resolving `version.workspace = true`
+    pub version: Option<String>,
+
+    /// Whether the crate can be published, ie it doesn't have `publish = false`
+    pub publish: bool,
+
+    /// The other workspace crates this crate depends on
+    ///
+    /// Only normal and build dependencies - dev-dependencies don't need a release when they change
+    pub dependencies: Vec<String>,
+}
+
+/// The members of a cargo workspace at a given commit
+#[derive(Debug, Clone, Default)]
+pub struct Workspace {
+    pub crates: Vec<WorkspaceCrate>,
+}
+
+impl Workspace {
+    /// Read the workspace from the manifests in a tree
+    ///
+    /// Returns `None` if there's no root `Cargo.toml` or it can't be parsed
+    pub fn from_tree(repo: &Repository, tree: &Tree) -> Option<Self> {
+        let root = read_manifest(repo, tree, Path::new(""))?;
+
+        let mut crates = vec![];
+
+        let workspace = root.get("workspace");
+        let workspace_version = workspace
+            .and_then(|workspace| workspace.get("package"))
+            .and_then(|package| package.get("version"))
+            .and_then(|version| version.as_str());
+
+        let mut push_crate = |manifest: &toml::Value, path: PathBuf| {
+            let Some(package) = manifest.get("package") else {
+                return;
+            };
+
+            let Some(name) = package.get("name").and_then(|name| name.as_str()) else {
+                return;
+            };
+
+            let version = match package.get("version") {
+                Some(toml::Value::String(version)) => Some(version.clone()),
+                Some(toml::Value::Table(_)) => workspace_version.map(String::from),
+                _ => None,
+            };
+
+            let publish = match package.get("publish") {
+                Some(toml::Value::Boolean(publish)) => *publish,
+                Some(toml::Value::Array(registries)) => !registries.is_empty(),
+                _ => true,
+            };
+
+            crates.push(WorkspaceCrate {
+                name: name.to_string(),
+                path,
+                version,
+                publish,
+                dependencies: dependency_names(manifest),
+            });
+        };
+
+        // The root itself can be a crate
+        push_crate(&root, PathBuf::new());
+
+        let patterns = |key: &str| -> Vec<String> {
+            workspace
+                .and_then(|workspace| workspace.get(key))
+                .and_then(|members| members.as_array())
+                .map(|members| {
+                    members
+                        .iter()
+                        .filter_map(|member| member.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let excluded = patterns("exclude");
+
+        for pattern in patterns("members") {
+            for dir in expand_member(repo, tree, &pattern) {
+                if excluded.iter().any(|exclude| is_excluded(&dir, exclude)) {
+                    continue;
+                }
+
+                if let Some(manifest) = read_manifest(repo, tree, &dir) {
+                    push_crate(&manifest, dir);
+                }
+            }
+        }
+
+        // We only care about the edges between workspace members
+        let members: HashSet<String> = crates.iter().map(|krate| krate.name.clone()).collect();
+        for krate in crates.iter_mut() {
+            krate.dependencies.retain(|dep| members.contains(dep));
+        }
+
+        Some(Self { crates })
+    }
+
+    /// Find every crate that depends on one of the given crates, directly or transitively
+    ///
+    /// The given crates themselves are never included, even if they depend on each other, since they need
+    /// a release anyway
+    pub fn dependents(&self, changed: &HashSet<String>) -> HashSet<String> {
+        let mut reverse: HashMap<&str, Vec<&str>> = HashMap::new();
+        for krate in self.crates.iter() {
+            for dep in krate.dependencies.iter() {
+                reverse.entry(dep).or_default().push(&krate.name);
+            }
+        }
+
+        let mut affected = HashSet::new();
+        let mut queue: Vec<&str> = changed.iter().map(String::as_str).collect();
+
+        while let Some(name) = queue.pop() {
+            for dependent in reverse.get(name).into_iter().flatten() {
+                if affected.insert(dependent.to_string()) {
+                    queue.push(dependent);
+                }
+            }
+        }
+
+        affected.retain(|name| !changed.contains(name));
+        affected
+    }
+
+    /// Find the crate that owns a file, picking the most deeply nested crate if they overlap
+    pub fn crate_for(&self, file: &Path) -> Option<&WorkspaceCrate> {
+        self.crates
+            .iter()
+            .filter(|krate| krate.owns(file))
+            .max_by_key(|krate| krate.path.components().count())
+    }
+}
+
+impl WorkspaceCrate {
+    /// Whether a file is part of this crate
+    ///
+    /// A package at the root of the repo shares its folder with everything else, like the readme, CI
+    /// config and examples, so it only owns its manifest, build script and `src`
+    fn owns(&self, file: &Path) -> bool {
+        match self.path.as_os_str().is_empty() {
+            true => {
+                file.starts_with("src")
+                    || file == Path::new("Cargo.toml")
+                    || file == Path::new("build.rs")
+            }
+            false => file.starts_with(&self.path),
+        }
+    }
+}
+
+/// Whether a member folder is left out by an entry of the workspace's `exclude`
+///
+/// Entries are globs like the members, and excluding a folder also excludes everything under it
+fn is_excluded(dir: &Path, exclude: &str) -> bool {
+    let exclude = exclude.trim_end_matches('/');
+
+    let matches = glob::Pattern::new(exclude).is_ok_and(|pattern| {
+        dir.ancestors()
+            .any(|ancestor| pattern.matches_path(ancestor))
+    });
+
+    matches || dir.starts_with(exclude)
+}
+
+/// Workspaces read from trees, keyed by the id of the tree
+///
+/// Walking history diffs every commit against its parent, so each tree gets looked at twice
+#[derive(Default)]
+pub struct WorkspaceCache {
+    workspaces: HashMap<Oid, Option<Rc<Workspace>>>,
+}
+
+impl WorkspaceCache {
+    pub fn get(&mut self, repo: &Repository, tree: &Tree) -> Option<Rc<Workspace>> {
+        self.workspaces
+            .entry(tree.id())
+            .or_insert_with(|| Workspace::from_tree(repo, tree).map(Rc::new))
+            .clone()
+    }
+}
+
+/// The crates that own the files changed between two trees
+///
+/// Removed files belong to the crate they were in before the change, so both sides get looked at
+pub fn changed_crates(repo: &Repository, old: &Tree, new: &Tree) -> HashSet<String> {
+    let mut crates = HashSet::new();
+
+    let Ok(diff) = repo.diff_tree_to_tree(Some(old), Some(new), None) else {
+        return crates;
+    };
+
+    let old_workspace = Workspace::from_tree(repo, old);
+    let new_workspace = Workspace::from_tree(repo, new);
+
+    for delta in diff.deltas() {
+        let sides = [
+            (&old_workspace, delta.old_file().path()),
+            (&new_workspace, delta.new_file().path()),
+        ];
+
+        for (workspace, file) in sides {
+            let krate = workspace
+                .as_ref()
+                .zip(file)
+                .and_then(|(workspace, file)| workspace.crate_for(file));
+
+            if let Some(krate) = krate {
+                crates.insert(krate.name.clone());
+            }
+        }
+    }
+
+    crates
+}
+
+/// Changes to the root manifest or the lockfile can affect every crate in the workspace
+pub fn is_workspace_file(file: &Path) -> bool {
+    file == Path::new("Cargo.toml") || file == Path::new("Cargo.lock")
+}
+
+fn read_manifest(repo: &Repository, tree: &Tree, dir: &Path) -> Option<toml::Value> {
+    let entry = tree.get_path(&dir.join("Cargo.toml")).ok()?;
+    let object = entry.to_object(repo).ok()?;
+    let blob = object.as_blob()?;
+    let contents = std::str::from_utf8(blob.content()).ok()?;
+
+    toml::from_str(contents).ok()
+}
+
+/// The names of the crates a manifest depends on, including target-specific dependencies
+///
+/// Renamed dependencies use the name of the actual package, not the name they're imported as
+fn dependency_names(manifest: &toml::Value) -> Vec<String> {
+    const KINDS: [&str; 2] = ["dependencies", "build-dependencies"];
+
+    let targets = manifest
+        .get("target")
+        .and_then(|targets| targets.as_table())
+        .into_iter()
+        .flat_map(|targets| targets.values());
+
+    std::iter::once(manifest)
+        .chain(targets)
+        .flat_map(|table| KINDS.iter().filter_map(|kind| table.get(kind)?.as_table()))
+        .flat_map(|deps| deps.iter())
+        .map(|(name, dep)| {
+            dep.get("package")
+                .and_then(|package| package.as_str())
+                .unwrap_or(name)
+                .to_string()
+        })
+        .collect()
+}
+
+/// Expand a workspace member like `packages/*` into the folders it matches in the tree
+fn expand_member(repo: &Repository, tree: &Tree, pattern: &str) -> Vec<PathBuf> {
+    let components: Vec<&str> = pattern
+        .trim_end_matches('/')
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect();
+
+    let mut out = vec![];
+    expand_components(repo, tree, PathBuf::new(), &components, &mut out);
+    out
+}
+
+fn expand_components(
+    repo: &Repository,
+    tree: &Tree,
+    prefix: PathBuf,
+    components: &[&str],
+    out: &mut Vec<PathBuf>,
+) {
+    let Some((component, rest)) = components.split_first() else {
+        out.push(prefix);
+        return;
+    };
+
+    let Ok(pattern) = glob::Pattern::new(component) else {
+        return;
+    };
+
+    for entry in tree.iter() {
+        if entry.kind() != Some(ObjectType::Tree) {
+            continue;
+        }
+
+        let Some(name) = entry.name() else {
+            continue;
+        };
+
+        if !pattern.matches(name) {
+            continue;
+        }
+
+        let Ok(object) = entry.to_object(repo) else {
+            continue;
+        };
+
+        if let Some(subtree) = object.as_tree() {
+            expand_components(repo, subtree, prefix.join(name), rest, out);
+        }
+    }
+}
+
+#[test]
+fn picks_the_most_nested_crate() {
+    let workspace = Workspace {
+        crates: vec![
+            WorkspaceCrate {
+                name: "dioxus".to_string(),
+                path: PathBuf::from("packages/dioxus"),
+                version: None,
+                publish: true,
+                dependencies: vec![],
+            },
+            WorkspaceCrate {
+                name: "dioxus-cli-config".to_string(),
+                path: PathBuf::from("packages/cli/config"),
+                version: None,
+                publish: true,
+                dependencies: vec![],
+            },
+            WorkspaceCrate {
+                name: "dioxus-cli".to_string(),
+                path: PathBuf::from("packages/cli"),
+                version: None,
+                publish: true,
+                dependencies: vec![],
+            },
+        ],
+    };
+
+    let name = |path: &str| {
+        workspace
+            .crate_for(Path::new(path))
+            .map(|k| k.name.as_str())
+    };
+
+    assert_eq!(name("packages/cli/src/main.rs"), Some("dioxus-cli"));
+    assert_eq!(
+        name("packages/cli/config/src/lib.rs"),
+        Some("dioxus-cli-config")
+    );
+    assert_eq!(name("packages/dioxus/Cargo.toml"), Some("dioxus"));
+    assert_eq!(name("examples/counter.rs"), None);
+}
+
+#[test]
+fn reads_members_and_the_root_package() {
+    let mut fixture = crate::fixtures::FixtureRepo::new();
+    let manifest = |name: &str| format!("[package]\nname = \"{name}\"\n");
+
+    let commit = fixture.commit(
+        "main",
+        "A workspace with a root package",
+        &[
+            (
+                "Cargo.toml",
+                &format!(
+                    "{}\n[workspace]\nmembers = [\"packages/*\"]\nexclude = [\"packages/*-old\"]\n",
+                    manifest("dioxus")
+                ),
+            ),
+            ("src/lib.rs", ""),
+            ("README.md", ""),
+            ("examples/counter.rs", ""),
+            ("packages/core/Cargo.toml", &manifest("dioxus-core")),
+            ("packages/core-old/Cargo.toml", &manifest("dioxus-core-old")),
+        ],
+    );
+
+    let tree = fixture.repo.find_commit(commit).unwrap().tree().unwrap();
+    let workspace = Workspace::from_tree(&fixture.repo, &tree).unwrap();
+
+    let mut names: Vec<_> = workspace.crates.iter().map(|k| k.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["dioxus", "dioxus-core"]);
+
+    let name = |path: &str| {
+        workspace
+            .crate_for(Path::new(path))
+            .map(|k| k.name.as_str())
+    };
+
+    assert_eq!(name("src/lib.rs"), Some("dioxus"));
+    assert_eq!(name("Cargo.toml"), Some("dioxus"));
+    assert_eq!(name("packages/core/src/lib.rs"), Some("dioxus-core"));
+    assert_eq!(name("README.md"), None);
+    assert_eq!(name("examples/counter.rs"), None);
+    assert_eq!(name(".github/workflows/main.yml"), None);
+}
+
+#[test]
+fn finds_transitive_dependents() {
+    let manifest: toml::Value = toml::from_str(
+        r#"
+        [package]
+        name = "dioxus-web"
+
+        [dependencies]
+        dioxus-core = { workspace = true }
+        html = { package = "dioxus-html", workspace = true }
+
+        [target.'cfg(target_arch = "wasm32")'.dependencies]
+        dioxus-interpreter-js = { workspace = true }
+
+        [dev-dependencies]
+        dioxus = { workspace = true }
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        dependency_names(&manifest),
+        vec!["dioxus-core", "dioxus-html", "dioxus-interpreter-js"]
+    );
+
+    let krate = |name: &str, dependencies: &[&str]| WorkspaceCrate {
+        name: name.to_string(),
+        path: PathBuf::from("packages").join(name),
+        version: None,
+        publish: true,
+        dependencies: dependencies.iter().map(|dep| dep.to_string()).collect(),
+    };
+
+    let workspace = Workspace {
+        crates: vec![
+            krate("dioxus-core", &[]),
+            krate("dioxus-html", &["dioxus-core"]),
+            krate("dioxus-web", &["dioxus-core", "dioxus-html"]),
+            krate("dioxus", &["dioxus-web", "dioxus-html"]),
+            krate("dioxus-autofmt", &[]),
+        ],
+    };
+
+    let changed = HashSet::from(["dioxus-html".to_string()]);
+    let mut affected: Vec<String> = workspace.dependents(&changed).into_iter().collect();
+    affected.sort();
+
+    assert_eq!(affected, vec!["dioxus", "dioxus-web"]);
+
+    // dioxus-html depends on dioxus-core, but it's already in the changed crates
+    let changed = HashSet::from(["dioxus-core".to_string(), "dioxus-html".to_string()]);
+    let mut affected: Vec<String> = workspace.dependents(&changed).into_iter().collect();
+    affected.sort();
+
+    assert_eq!(affected, vec!["dioxus", "dioxus-web"]);
+}
@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The contents of `doxie.toml`
+///
+/// This describes the org the bot is running against. Everything here has a default that matches the
+/// dioxus org, so an empty (or missing) file behaves exactly like the bot always has.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DoxieConfig {
+    /// The repo we walk for releases and post status comments to, in the form of `owner/repo`
+    pub main_repo: String,
+
+    /// Every repo whose open PRs we track, in the form of `owner/repo`
+    pub repos: Vec<String>,
+
+    pub releases: ReleaseConfig,
+
+    pub workspace: WorkspaceConfig,
+
+    pub backports: BackportConfig,
+
+    pub churn: ChurnConfig,
+
+    pub github: GitHubConfig,
+
+    pub benchmarks: BenchConfig,
+
+    pub sizes: SizeConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReleaseConfig {
+    /// The pattern release tags follow, where `{version}` is replaced with the semver version
+    ///
+    /// IE `v{version}` for `v0.5.1` or `dioxus-v{version}` for `dioxus-v0.5.1`
+    pub tag_pattern: String,
+
+    /// The release lines we report on, in the form of `major.minor`
+    ///
+    /// Leave this empty to report on every line discovered from the tags
+    pub lines: Vec<String>,
+
+    /// Skip any discovered release lines older than this one, in the form of `major.minor`
+    pub oldest_line: Option<String>,
+
+    /// The pattern stable branches follow, where `{major}` and `{minor}` are replaced with the line
+    ///
+    /// Fixes that get cherry-picked onto these branches are marked as backported
+    pub branch_pattern: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceConfig {
+    /// The directories that contain the crates of the workspace
+    ///
+    /// Crates are normally found by reading the workspace's `Cargo.toml`. If a commit has no workspace,
+    /// the first folder after one of these roots is considered the name of the package
+    pub package_roots: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackportConfig {
+    /// How much each PR label adds to the score of a backport candidate
+    pub label_scores: HashMap<String, i64>,
+
+    /// PRs with any of these labels are never backport candidates
+    pub breaking_labels: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChurnConfig {
+    /// Globs of files that don't count towards the lines changed, relative to the root of the repo
+    ///
+    /// Lockfiles, snapshots and generated code would otherwise drown out the real changes
+    pub exclude: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitHubConfig {
+    /// The most items we read from a single listing, ie the open PRs of a repo
+    ///
+    /// Every 100 items costs a request, so this keeps a repo with a huge backlog from eating the budget
+    pub max_items: usize,
+
+    /// Once fewer requests than this are left in the hourly budget, wait for it to reset before sending more
+    ///
+    /// This leaves some room for anything else sharing the token, like the other steps of a workflow
+    pub rate_limit_reserve: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BenchConfig {
+    /// The commands that run the criterion benchmarks, from the root of the repo
+    ///
+    /// These are split on whitespace, so arguments can't contain spaces
+    pub commands: Vec<String>,
+
+    /// Where criterion writes its results, relative to the root of the repo
+    pub criterion_dir: String,
+
+    /// How many times each commit runs its benchmarks when comparing against a baseline
+    ///
+    /// The runs alternate between the two, so noise from the runner hits both sides about equally
+    pub rounds: usize,
+
+    /// How much slower or faster a benchmark has to get to be reported, ie `0.05` for 5%
+    pub threshold: f64,
+
+    /// The p-value a change has to stay under to be reported
+    pub significance: f64,
+
+    /// How many commits on each side of a step in the history have to agree on the new level
+    ///
+    /// A single noisy run can't move the median of a window, so this is what keeps blips from alerting
+    pub history_window: usize,
+
+    /// How many median absolute deviations the level has to move by to count as a step
+    pub step_mads: f64,
+
+    /// How many commits of stats each branch keeps in its history, dropping the oldest ones first
+    pub history_length: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SizeConfig {
+    /// The examples whose wasm size we track, by the name `cargo build --example` knows them by
+    pub examples: Vec<String>,
+
+    /// Any extra arguments the examples need to build for the web, ie `--features web`
+    pub build_args: Vec<String>,
+
+    /// Every opt-level each example is built at. Each one is measured both straight out of cargo and
+    /// after going through `wasm-opt` at the same level
+    pub opt_levels: Vec<String>,
+
+    /// Where the size builds go, relative to the root of the repo. Each opt-level gets its own target
+    /// dir so switching between them doesn't rebuild everything
+    pub target_dir: String,
+
+    /// How many of the biggest functions of each build to keep
+    pub top_symbols: usize,
+}
+
+/// A repo that isn't in the form of `owner/repo`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidRepo(pub String);
+
+impl std::fmt::Display for InvalidRepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` is not in the form of owner/repo", self.0)
+    }
+}
+
+impl std::error::Error for InvalidRepo {}
+
+impl DoxieConfig {
+    pub fn from_toml(raw: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(raw)
+    }
+
+    /// Split the main repo into its owner and name
+    pub fn main_owner_and_repo(&self) -> Result<(&str, &str), InvalidRepo> {
+        match self.main_repo.split_once('/') {
+            Some((owner, repo)) if !owner.is_empty() && !repo.is_empty() && !repo.contains('/') => {
+                Ok((owner, repo))
+            }
+            _ => Err(InvalidRepo(self.main_repo.clone())),
+        }
+    }
+
+    /// A link to a PR on the main repo
+    pub fn pr_url(&self, id: impl std::fmt::Display) -> String {
+        format!("https://github.com/{}/pull/{id}", self.main_repo)
+    }
+
+    /// A link to a package's folder on the main branch of the main repo
+    ///
+    /// Uses the crate's real path if we know it, otherwise guesses that it's in the first package root
+    pub fn package_url(&self, package: &str, path: Option<&str>) -> String {
+        if let Some(path) = path {
+            return format!("https://github.com/{}/tree/main/{path}", self.main_repo);
+        }
+
+        let root = self
+            .workspace
+            .package_roots
+            .first()
+            .map(String::as_str)
+            .unwrap_or("packages");
+
+        format!(
+            "https://github.com/{}/tree/main/{root}/{package}",
+            self.main_repo
+        )
+    }
+}
+
+impl ReleaseConfig {
+    /// Get the name of the tag for a given version
+    pub fn tag_for(&self, version: &str) -> String {
+        self.tag_pattern.replace("{version}", version)
+    }
+
+    /// Get the name of the stable branch for a release line
+    pub fn branch_for(&self, major: u64, minor: u64) -> String {
+        self.branch_pattern
+            .replace("{major}", &major.to_string())
+            .replace("{minor}", &minor.to_string())
+    }
+
+    /// Parse the `lines` into `(major, minor)` pairs, skipping anything that's malformed
+    pub fn parsed_lines(&self) -> Vec<(usize, usize)> {
+        self.lines
+            .iter()
+            .filter_map(|line| parse_line(line))
+            .collect()
+    }
+
+    /// Parse the `oldest_line` into a `(major, minor)` pair
+    pub fn parsed_oldest_line(&self) -> Option<(usize, usize)> {
+        self.oldest_line.as_deref().and_then(parse_line)
+    }
+}
+
+fn parse_line(line: &str) -> Option<(usize, usize)> {
+    let (major, minor) = line.split_once('.')?;
+    Some((major.trim().parse().ok()?, minor.trim().parse().ok()?))
+}
+
+impl Default for DoxieConfig {
+    fn default() -> Self {
+        Self {
+            main_repo: "dioxuslabs/dioxus".to_string(),
+            repos: [
+                "dioxuslabs/dioxus",
+                "dioxuslabs/dioxus-template",
+                "dioxuslabs/docsite",
+                "dioxuslabs/blitz",
+                "dioxuslabs/components",
+                "dioxuslabs/sdk",
+                "dioxuslabs/collect-assets",
+                "dioxuslabs/include_mdbook",
+                "dioxuslabs/example-projects",
+                "dioxuslabs/awesome-dioxus",
+                "jkelleyrtp/stylo-dioxus",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect(),
+            releases: ReleaseConfig::default(),
+            workspace: WorkspaceConfig::default(),
+            backports: BackportConfig::default(),
+            churn: ChurnConfig::default(),
+            github: GitHubConfig::default(),
+            benchmarks: BenchConfig::default(),
+            sizes: SizeConfig::default(),
+        }
+    }
+}
+
+impl Default for ReleaseConfig {
+    fn default() -> Self {
+        Self {
+            tag_pattern: "v{version}".to_string(),
+            lines: vec![],
+            oldest_line: Some("0.4".to_string()),
+            branch_pattern: "v{major}.{minor}".to_string(),
+        }
+    }
+}
+
+impl Default for BackportConfig {
+    fn default() -> Self {
+        Self {
+            label_scores: [("bug", 10), ("regression", 20), ("fix", 5), ("docs", 1)]
+                .into_iter()
+                .map(|(label, score)| (label.to_string(), score))
+                .collect(),
+            breaking_labels: vec!["breaking".to_string()],
+        }
+    }
+}
+
+impl Default for ChurnConfig {
+    fn default() -> Self {
+        Self {
+            exclude: ["Cargo.lock", "**/*.snap", "**/*.min.js"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+}
+
+impl Default for GitHubConfig {
+    fn default() -> Self {
+        Self {
+            max_items: 1000,
+            rate_limit_reserve: 100,
+        }
+    }
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            commands: vec!["cargo bench --workspace".to_string()],
+            criterion_dir: "target/criterion".to_string(),
+            rounds: 5,
+            threshold: 0.05,
+            significance: 0.05,
+            history_window: 5,
+            step_mads: 3.0,
+            history_length: 500,
+        }
+    }
+}
+
+impl Default for SizeConfig {
+    fn default() -> Self {
+        Self {
+            examples: vec![],
+            build_args: vec![],
+            opt_levels: ["s", "z", "3"].into_iter().map(String::from).collect(),
+            target_dir: "target/doxie-size".to_string(),
+            top_symbols: 20,
+        }
+    }
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self {
+            package_roots: vec!["packages".to_string()],
+        }
+    }
+}
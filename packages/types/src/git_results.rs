@@ -3,27 +3,60 @@ use std::collections::{HashMap, HashSet};
 use octocrab_models::pulls::PullRequest;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChangedVersions {
-    pub version: HashMap<usize, MinorVersionChanged>,
+    /// Every release line discovered from the tags, keyed by `major.minor`
+    pub version: HashMap<String, MinorVersionChanged>,
+
+    /// The folder of every crate in the workspace at HEAD, keyed by crate name
+    #[serde(default)]
+    pub crate_paths: HashMap<String, String>,
+
+    /// The release lines and commits that failed to collect when running with `--keep-going`
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MinorVersionChanged {
+    #[serde(default)]
+    pub major: usize,
+
+    /// The minor version of this release line
     pub version: usize,
+
     pub patch_versions: Vec<PatchVersionChanged>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PatchVersionChanged {
+    /// The patch version of this release
     pub version: usize,
 
+    /// The prerelease identifier if this is a prerelease, ie `alpha.1` for `0.6.0-alpha.1`
+    #[serde(default)]
+    pub pre: Option<String>,
+
+    /// Whether this version has been tagged yet
+    ///
+    /// The commits of a published version are the ones since the previous release or prerelease.
+    /// The unpublished version collects everything that landed after the last tag.
     pub published: bool,
 
+    /// Every crate directly changed by the commits of this version
+    #[serde(default)]
+    pub changed_packages: HashSet<String>,
+
+    /// Crates that weren't changed themselves but depend on one that was, so they need a release too
+    ///
+    /// Determined with the workspace dependency graph at the release tag (or HEAD if unpublished)
+    #[serde(default)]
+    pub affected_packages: HashSet<String>,
+
     pub commits: Vec<PrCommit>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PrCommit {
     /// The summary of the PR
     pub summary: String,
@@ -31,14 +64,46 @@ pub struct PrCommit {
     /// The `#id` of the PR - so you can go to github.com/dioxuslabs/dioxus/pull/id
     pub id: Option<usize>,
 
-    /// The changed packages of the PR
-    /// Determined by walking the diff and saving anything under "packages"
+    /// The names of the crates changed by the PR
+    /// Determined by mapping the files in the diff to the workspace members in the commit's `Cargo.toml`
     /// Not guaranteed to be sorted, so you probably wanna sort this when rendering it
     pub changed_packages: HashSet<String>,
 
+    /// The crates that depend on a changed package, directly or transitively, and weren't changed themselves
+    #[serde(default)]
+    pub affected_packages: HashSet<String>,
+
+    /// Whether the root `Cargo.toml` or `Cargo.lock` changed, which can affect every crate
+    #[serde(default)]
+    pub affects_workspace: bool,
+
     /// The hash of the commit - so you can find it on github.com/dioxuslabs/dioxus/commit/hash
     pub commit_hash: String,
 
+    /// The stable tags (or branches, if not yet released) this commit was backported to
+    ///
+    /// Matched by PR number, `cherry picked from` trailers, and patch-id. Empty if it never left main.
+    #[serde(default)]
+    pub backported_to: Vec<String>,
+
+    /// What `cargo semver-checks` found in the crates this commit changed, compared to the last release
+    ///
+    /// Only filled in for unreleased commits, and only when the checks were asked for
+    #[serde(default)]
+    pub semver_findings: Vec<SemverFinding>,
+
+    /// The issues the PR closes or references, from the commit message and the github timeline
+    #[serde(default)]
+    pub linked_issues: Vec<LinkedIssue>,
+
+    /// The lines changed by the commit, leaving out the files excluded in the churn config
+    #[serde(default)]
+    pub churn: ChurnStats,
+
+    /// The lines changed in each crate, keyed by the crate name
+    #[serde(default)]
+    pub churn_by_crate: HashMap<String, ChurnStats>,
+
     /// The index of this commit in the log, relative to the base of the release commit
     /// IE the first commit will be "0", the second "1", etc for just this PatchVersionChanged
     ///
@@ -46,14 +111,180 @@ pub struct PrCommit {
     pub head_index: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenPrMap {
     pub prs: HashMap<String, OpenPrs>,
+
+    /// The repos that failed to list when running with `--keep-going`
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OpenPrs {
     pub repo: String,
 
     pub prs: Vec<PullRequest>,
+
+    /// How many PRs are open, which is more than `prs` holds if the listing hit `max_items`
+    #[serde(default)]
+    pub total_count: usize,
+
+    /// What `cargo semver-checks` found in each PR, keyed by the PR number
+    ///
+    /// PRs that weren't checked are missing, PRs that passed have an empty list
+    #[serde(default)]
+    pub semver_findings: HashMap<u64, Vec<SemverFinding>>,
+
+    /// The issues each PR closes or references, keyed by the PR number
+    #[serde(default)]
+    pub linked_issues: HashMap<u64, Vec<LinkedIssue>>,
+}
+
+/// Something a collector skipped over instead of failing the whole run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// What was being collected, ie `commit 1a2b3c` or `repo dioxuslabs/blitz`
+    pub scope: String,
+
+    pub message: String,
+}
+
+impl OpenPrs {
+    /// Whether `prs` has every open PR of the repo
+    pub fn is_complete(&self) -> bool {
+        self.prs.len() >= self.total_count
+    }
+}
+
+/// How much a change touched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChurnStats {
+    pub insertions: usize,
+    pub deletions: usize,
+    pub files_changed: usize,
+}
+
+impl ChurnStats {
+    /// The total number of lines touched, counting both insertions and deletions
+    pub fn lines(&self) -> usize {
+        self.insertions + self.deletions
+    }
+
+    pub fn add(&mut self, other: &ChurnStats) {
+        self.insertions += other.insertions;
+        self.deletions += other.deletions;
+        self.files_changed += other.files_changed;
+    }
+}
+
+/// An issue that a PR is connected to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkedIssue {
+    /// The `owner/repo` the issue lives in
+    pub repo: String,
+
+    pub number: usize,
+
+    /// Whether the PR closes the issue with a keyword like `fixes #123`, or just references it
+    pub closes: bool,
+}
+
+impl LinkedIssue {
+    pub fn url(&self) -> String {
+        format!("https://github.com/{}/issues/{}", self.repo, self.number)
+    }
+}
+
+/// A single lint that `cargo semver-checks` reported
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SemverFinding {
+    /// The crate the lint was reported for
+    #[serde(rename = "crate")]
+    pub krate: String,
+
+    /// The id of the lint, ie `function_missing`
+    pub lint: String,
+
+    pub severity: SemverSeverity,
+
+    /// The item that broke, ie `function dioxus_core::prelude::use_hook`
+    ///
+    /// `None` if the lint didn't point at a specific item
+    pub item: Option<String>,
+
+    /// The short description of the lint, ie `pub fn removed or renamed`
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SemverSeverity {
+    /// The change needs a new major version (or minor, before 1.0)
+    Failure,
+
+    /// The lint only warns - the change might still be fine to ship in a patch
+    Warning,
+}
+
+/// PRs merged to main since the last stable release that still need to be backported
+///
+/// Sorted by score so the release manager can work through the list from the top
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackportCandidates {
+    /// The last stable release tag, ie `v0.5.1`
+    pub stable_tag: String,
+
+    pub candidates: Vec<BackportCandidate>,
+
+    /// The commits that failed to collect and the PRs whose labels couldn't be fetched when running
+    /// with `--keep-going`
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackportCandidate {
+    pub commit: PrCommit,
+
+    /// The labels of the PR on github
+    pub labels: Vec<String>,
+
+    /// Whether `cargo semver-checks` considers the change compatible with the stable release
+    ///
+    /// `None` if the check wasn't run or couldn't run
+    pub semver_compatible: Option<bool>,
+
+    /// How badly we want this backported - higher is more important
+    pub score: i64,
+}
+
+/// Every publishable crate in the workspace compared against its newest version on crates.io
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReleaseQueue {
+    /// Sorted so the crates that need a release come first, then by name
+    pub crates: Vec<CrateReleaseStatus>,
+
+    /// The commits that failed to collect when running with `--keep-going`
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrateReleaseStatus {
+    pub name: String,
+
+    /// The version in the crate's `Cargo.toml` at HEAD
+    pub local_version: Option<String>,
+
+    /// The newest version on crates.io that hasn't been yanked, `None` if it was never published
+    pub published_version: Option<String>,
+
+    /// Whether the crate has changed since it was published, or was never published at all
+    ///
+    /// Also set if we couldn't find the commit the published version came from, which gets a diagnostic
+    pub needs_release: bool,
+
+    /// The commits that touched the crate since the published version
+    pub commits: Vec<PrCommit>,
 }
@@ -1,5 +1,10 @@
 use std::collections::HashMap;
 
+use serde::{Deserialize, Serialize};
+
+use crate::Diagnostic;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Fullstats {
     /// A map of branch to its stat history
     ///
@@ -9,9 +14,47 @@ pub struct Fullstats {
     pub stats: HashMap<String, BranchStats>,
 
     /// when was this stats bundle last updated?
+    ///
+    /// The commit time of the last stats recorded, in seconds since the unix epoch
     pub last_updated: String,
 }
 
+impl Fullstats {
+    /// The stats of a commit on any branch
+    pub fn find(&self, sha: &str) -> Option<&CommitStats> {
+        self.stats
+            .values()
+            .flat_map(|branch| branch.stats.iter())
+            .find(|stats| stats.sha == sha)
+    }
+
+    /// Add the stats of a commit to a branch's history, replacing any we had for it before
+    ///
+    /// The history stays in the order the commits landed, even if their stats come in out of order, and
+    /// only keeps the newest `max_len` commits
+    pub fn record(&mut self, branch: &str, stats: CommitStats, max_len: usize) {
+        let history = self
+            .stats
+            .entry(branch.to_string())
+            .or_insert_with(|| BranchStats {
+                name: branch.to_string(),
+                stats: vec![],
+            });
+
+        history.stats.retain(|old| old.sha != stats.sha);
+
+        // After any commits that landed at the same time, so those keep the order they came in
+        let index = history
+            .stats
+            .partition_point(|old| old.commit_time <= stats.commit_time);
+        history.stats.insert(index, stats);
+
+        let extra = history.stats.len().saturating_sub(max_len);
+        history.stats.drain(..extra);
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct BranchStats {
     /// The name of the branch
     pub name: String,
@@ -37,39 +80,219 @@ pub struct BranchStats {
 /// The hashmaps are used to map a particular benchmark to the stats for that benchmark
 ///
 /// This lets us add new benchmarks over time to test different things
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CommitStats {
     pub pr_name: String,
 
     pub sha: String,
 
+    /// When the commit landed on its branch, in seconds since the unix epoch
+    ///
+    /// This is the committer time, which for a PR is when it got merged, so it orders the history
+    #[serde(default)]
+    pub commit_time: i64,
+
     pub perf: HashMap<String, PerfStats>,
 
     /// How big is the .wasm bundle, the .apps, the full bundle, etc.
     pub compile_size: HashMap<String, CompileSizeStats>,
 
     pub compile_time: HashMap<String, CompiletimeStats>,
+
+    /// The benchmarks that failed to run or whose results couldn't be read
+    #[serde(default)]
+    pub diagnostics: Vec<Diagnostic>,
 }
 
+/// What criterion measured for a single benchmark, with every time in nanoseconds per iteration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PerfStats {
     pub name: String,
 
+    /// The mean time of an iteration, as it was measured on this runner
     pub raw_walltime: f64,
 
     /// Not every runnner is made equally, so we attempt to normalize the walltime by running with the
     /// previous main commit and then the current one.
-    pub normalized_walltime: f64,
+    ///
+    /// This is the walltime as a ratio of the baseline's on the same runner, so `1.0` means nothing
+    /// changed and `1.1` means 10% slower. `None` until the benchmark has been compared against a baseline
+    pub normalized_walltime: Option<f64>,
+
+    pub mean: Estimate,
+
+    pub median: Estimate,
+
+    pub std_dev: Estimate,
 }
 
+/// A point estimate and the confidence interval criterion bootstrapped around it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Estimate {
+    pub point: f64,
+    pub lower_bound: f64,
+    pub upper_bound: f64,
+
+    /// How sure criterion is that the real value is between the bounds, ie `0.95`
+    pub confidence_level: f64,
+}
+
+/// The benchmarks of a commit compared against a baseline, in interleaved rounds on the same runner
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PerfComparison {
+    /// The sha of the commit we compared against, usually the last commit on main
+    pub baseline: String,
+
+    /// The sha of the commit being measured
+    pub candidate: String,
+
+    /// How many times each side ran its benchmarks
+    pub rounds: usize,
+
+    /// How much slower or faster a benchmark has to get to count as a change, ie `0.05` for 5%
+    pub threshold: f64,
+
+    /// The p-value a change needs to stay under to count as more than noise
+    pub significance: f64,
+
+    /// Every benchmark both commits have, sorted by name
+    pub benchmarks: Vec<BenchmarkChange>,
+}
+
+impl PerfComparison {
+    pub fn regressions(&self) -> impl Iterator<Item = &BenchmarkChange> {
+        self.benchmarks
+            .iter()
+            .filter(|change| change.verdict == PerfVerdict::Regression)
+    }
+
+    pub fn improvements(&self) -> impl Iterator<Item = &BenchmarkChange> {
+        self.benchmarks
+            .iter()
+            .filter(|change| change.verdict == PerfVerdict::Improvement)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchmarkChange {
+    pub name: String,
+
+    /// The mean walltime of each round, in nanoseconds per iteration
+    pub baseline_rounds: Vec<f64>,
+    pub candidate_rounds: Vec<f64>,
+
+    /// The mean walltime of the candidate over the baseline's
+    pub ratio: f64,
+
+    /// How likely a difference this big would be from noise alone, from a Welch's t-test over the rounds
+    pub p_value: f64,
+
+    pub verdict: PerfVerdict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PerfVerdict {
+    Regression,
+    Improvement,
+
+    /// Either within the threshold or not significant
+    Unchanged,
+}
+
+/// The health of the main repo at a glance, saved to `status.json` for the status page
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RepoStatus {
+    /// The repo in the form of `owner/repo`
+    pub repo: String,
+
+    /// How many PRs are open
+    pub open_prs: usize,
+
+    /// The newest stats `doxie stats` recorded for each branch, keyed by the name of the branch
+    pub latest_stats: HashMap<String, CommitStats>,
+
+    pub regressions: RegressionReport,
+}
+
+/// The step changes found in the benchmark history of every branch
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RegressionReport {
+    /// Sorted by branch, then benchmark, then where they happened
+    pub change_points: Vec<ChangePoint>,
+}
+
+/// A commit after which a benchmark got lastingly slower or faster
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangePoint {
+    pub branch: String,
+
+    pub benchmark: String,
+
+    /// The first commit of the new level
+    pub sha: String,
+
+    pub pr_name: String,
+
+    /// The PR the commit was merged from, if its message says
+    pub pr: Option<usize>,
+
+    /// The median walltime of the commits before and after the step, in nanoseconds per iteration
+    pub before: f64,
+    pub after: f64,
+
+    /// `after` over `before`
+    pub ratio: f64,
+
+    /// Only ever a regression or an improvement
+    pub verdict: PerfVerdict,
+}
+
+/// The size of one example's wasm, built one particular way
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CompileSizeStats {
+    /// The example and how it was built, ie `todomvc/z+wasm-opt`
+    pub name: String,
+
+    pub example: String,
+
+    /// The opt-level of the release profile, ie `s`, `z` or `3`
+    pub opt_level: String,
+
+    /// Whether the wasm went through `wasm-opt` at the same opt-level after cargo built it
+    pub wasm_opt: bool,
+
+    /// The bytes of the `.wasm`, as the browser would download them without compression
+    pub raw_size: u64,
+
+    /// The size compressed with gzip and brotli at their highest levels, which is what actually goes
+    /// over the wire from most hosts
+    pub gzip_size: u64,
+    pub brotli_size: u64,
+
+    /// The bytes of code each crate contributed, worked out from the function names in the wasm
+    ///
+    /// Data segments count towards `[data]` and functions that aren't from a rust crate towards
+    /// `[other]`. This is empty for builds that went through `wasm-opt`, since it strips the names.
+    #[serde(default)]
+    pub crates: HashMap<String, u64>,
+
+    /// The biggest functions, biggest first
+    #[serde(default)]
+    pub top_symbols: Vec<SymbolSize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SymbolSize {
+    /// The demangled name of the function, without its hash
     pub name: String,
 
-    /// Cargo build --target wasm32 --release
-    pub raw_debug_wasm_size: u64,
+    /// The crate the function belongs to, or `[other]`
+    pub krate: String,
 
-    pub optimized_wasm_size: u64,
-    //
+    pub size: u64,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CompiletimeStats {
     pub name: String,
 
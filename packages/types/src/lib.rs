@@ -7,4 +7,7 @@ pub use health::*;
 mod git_results;
 pub use git_results::*;
 
+mod config;
+pub use config::*;
+
 pub use octocrab_models;
@@ -0,0 +1,15 @@
+//! Copy `data/commits.json` next to the build so the UI still builds without it
+//!
+//! The blob only exists once `doxie commits` has been run against a clone of the dioxus repo, and
+//! `include_str!` can't make a file optional - an empty copy tells the UI there's nothing to show yet.
+
+use std::path::PathBuf;
+
+fn main() {
+    let blob = concat!(env!("CARGO_MANIFEST_DIR"), "/../../data/commits.json");
+    println!("cargo:rerun-if-changed={blob}");
+
+    let raw = std::fs::read_to_string(blob).unwrap_or_default();
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    std::fs::write(out_dir.join("commits.json"), raw).expect("failed to copy commits.json");
+}
@@ -1,9 +1,9 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use dioxus::prelude::*;
 use doxie_types::{
-    octocrab_models::pulls::PullRequest, ChangedVersions, MinorVersionChanged, OpenPrMap,
-    PatchVersionChanged, PrCommit,
+    octocrab_models::pulls::PullRequest, ChangedVersions, ChurnStats, DoxieConfig, LinkedIssue,
+    MinorVersionChanged, OpenPrMap, PatchVersionChanged, PrCommit, SemverFinding, SemverSeverity,
 };
 
 fn main() {
@@ -12,6 +12,12 @@ fn main() {
     launch(App);
 }
 
+/// The same doxie.toml the bot reads, so links point at the right org
+fn config() -> DoxieConfig {
+    let raw = include_str!("../../../doxie.toml");
+    DoxieConfig::from_toml(raw).expect("failed to parse doxie.toml")
+}
+
 #[component]
 fn App() -> Element {
     // Build cool things ✌️
@@ -45,7 +51,9 @@ fn OpenPrs() -> Element {
                 .sort_by(|a, b| a.updated_at.cmp(&b.updated_at).reverse());
         }
 
-        let render_pr = move |pr: &mut PullRequest| {
+        let render_pr = move |pr: &mut PullRequest,
+                              findings: Option<&Vec<SemverFinding>>,
+                              issues: Option<&Vec<LinkedIssue>>| {
             let pr_num = pr.number;
 
             rsx! {
@@ -67,6 +75,12 @@ fn OpenPrs() -> Element {
                             }
                             pre { "Description: {pr.body.as_deref().unwrap_or_default()}" }
                         }
+                        if let Some(issues) = issues {
+                            LinkedIssues { issues: issues.clone() }
+                        }
+                        if let Some(findings) = findings {
+                            SemverFindings { findings: findings.clone() }
+                        }
                     }
                 }
             }
@@ -76,8 +90,11 @@ fn OpenPrs() -> Element {
             ul {
                 for mut pr in sorted_pr_list {
                     h3 { "{pr.repo}" }
-                    for pr in pr.prs.iter_mut() {
-                        {render_pr(pr)}
+                    if !pr.is_complete() {
+                        p { class: "capped", "Showing {pr.prs.len()} of {pr.total_count} open PRs" }
+                    }
+                    for open in pr.prs.iter_mut() {
+                        {render_pr(open, pr.semver_findings.get(&open.number), pr.linked_issues.get(&open.number))}
                     }
                 }
             }
@@ -89,91 +106,319 @@ fn OpenPrs() -> Element {
     }
 }
 
+/// Which commits to show based on whether they've made it onto a stable branch
+#[derive(Clone, Copy, PartialEq)]
+enum BackportFilter {
+    All,
+    Backported,
+    NotBackported,
+}
+
+impl BackportFilter {
+    fn matches(self, commit: &PrCommit) -> bool {
+        match self {
+            BackportFilter::All => true,
+            BackportFilter::Backported => !commit.backported_to.is_empty(),
+            BackportFilter::NotBackported => commit.backported_to.is_empty(),
+        }
+    }
+}
+
+/// The order releases and their commits are listed in
+#[derive(Clone, Copy, PartialEq)]
+enum SortOrder {
+    /// Newest release first, and the commits in the order they landed
+    Release,
+
+    /// The releases and commits that changed the most lines first
+    Churn,
+}
+
 fn PrsWithChanges() -> Element {
+    let mut filter = use_signal(|| BackportFilter::All);
+    let mut sort_order = use_signal(|| SortOrder::Release);
+
     let data = use_signal(|| {
-        let raw = include_str!("../../../data/commits.json");
+        // Empty when `doxie commits` hasn't been run yet, see the build script
+        let raw = include_str!(concat!(env!("OUT_DIR"), "/commits.json"));
+        if raw.trim().is_empty() {
+            return None;
+        }
+
         let commits: ChangedVersions =
             serde_json::from_str(raw).expect("failed to parse commits.json");
 
         let mut versions = commits.version.into_values().collect::<Vec<_>>();
 
         // sort so we get the most recent version
-        versions.sort_by(|a, b| a.version.cmp(&b.version).reverse());
+        versions.sort_by(|a, b| (a.major, a.version).cmp(&(b.major, b.version)).reverse());
+
+        Some((versions, commits.crate_paths))
+    });
 
-        let render_minor = move |version: MinorVersionChanged| {
-            let minor_version = version.version;
+    let Some((versions, crate_paths)) = data() else {
+        return rsx! {
+            p { class: "missing-data",
+                "No release data yet - run "
+                code { "doxie commits" }
+                " against a clone of the dioxus repo to fill this in."
+            }
+        };
+    };
+    let config = config();
+    let filter_value = filter();
+    let sort_value = sort_order();
 
-            let render_patch = move |patch: PatchVersionChanged| {
-                let render_commit = move |commit: PrCommit| {
-                    // note that we're ignore direct commits to main... could get confusing
-                    let id = commit.id?;
+    let render_minor = move |version: MinorVersionChanged| {
+        let major_version = version.major;
+        let minor_version = version.version;
+        let config = &config;
+        let crate_paths = &crate_paths;
 
-                    rsx! {
-                        li { class: "pr-item",
-                            a { href: "https://github.com/dioxuslabs/dioxus/pull/{id}",
-                                "{commit.summary}"
-                            }
+        let render_patch = move |mut patch: PatchVersionChanged| {
+            patch.commits.retain(|commit| filter_value.matches(commit));
+
+            if sort_value == SortOrder::Churn {
+                patch
+                    .commits
+                    .sort_by_key(|commit| std::cmp::Reverse(commit.churn.lines()));
+            }
+
+            let render_commit = move |commit: PrCommit| {
+                // note that we're ignore direct commits to main... could get confusing
+                let id = commit.id?;
+                let backported_to = commit.backported_to.join(", ");
+
+                rsx! {
+                    li { class: "pr-item",
+                        a { href: config.pr_url(id),
+                            "{commit.summary}"
+                        }
+                        if !commit.backported_to.is_empty() {
+                            span { class: "backported", " (backported to {backported_to})" }
+                        }
+                        span { class: "churn", " +{commit.churn.insertions} -{commit.churn.deletions}" }
+                        LinkedIssues { issues: commit.linked_issues.clone() }
+                        if !commit.semver_findings.is_empty() {
+                            SemverFindings { findings: commit.semver_findings.clone() }
                         }
                     }
-                };
+                }
+            };
 
-                let changed_packages = patch
-                    .commits
-                    .iter()
-                    .flat_map(|commit| commit.changed_packages.iter())
-                    .collect::<HashSet<&String>>();
+            let changed_packages = patch
+                .commits
+                .iter()
+                .flat_map(|commit| commit.changed_packages.iter())
+                .collect::<HashSet<&String>>();
+
+            // Crates that only need a release because something they depend on changed
+            let affected_packages = patch
+                .commits
+                .iter()
+                .flat_map(|commit| commit.affected_packages.iter())
+                .filter(|package| !changed_packages.contains(package))
+                .collect::<HashSet<&String>>();
+
+            // Where the big changes of this release landed
+            let mut churn = ChurnStats::default();
+            let mut churn_by_crate: HashMap<&String, ChurnStats> = HashMap::new();
+            for commit in patch.commits.iter() {
+                churn.add(&commit.churn);
+                for (krate, stats) in commit.churn_by_crate.iter() {
+                    churn_by_crate.entry(krate).or_default().add(stats);
+                }
+            }
 
-                let mut changed_packages = changed_packages.into_iter().collect::<Vec<_>>();
-                changed_packages.sort();
+            let mut biggest_changes = churn_by_crate.into_iter().collect::<Vec<_>>();
+            biggest_changes.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.lines()));
+            biggest_changes.truncate(5);
 
+            // The bugs this release fixes
+            let mut fixed_issues: Vec<LinkedIssue> = vec![];
+            for issue in patch
+                .commits
+                .iter()
+                .flat_map(|commit| commit.linked_issues.iter())
+            {
+                if issue.closes && !fixed_issues.contains(issue) {
+                    fixed_issues.push(issue.clone());
+                }
+            }
+
+            let mut changed_packages = changed_packages.into_iter().collect::<Vec<_>>();
+            changed_packages.sort();
+
+            let mut affected_packages = affected_packages.into_iter().collect::<Vec<_>>();
+            affected_packages.sort();
+
+            let render_packages = move |packages: Vec<&String>| {
                 rsx! {
-                    div {
-                        h4 {
-                            "v0.{minor_version}.{patch.version}"
-                            match patch.published {
-                                true => rsx!{ span { class: "published", " - (Published)" } },
-                                false => rsx!{ span { class: "unpublished", " - (Unpublished)" } },
-                            }
+                    for package in packages {
+                        a {
+                            href: config.package_url(package, crate_paths.get(package).map(String::as_str)),
+                            target: "_blank",
+                            "{package},"
+                        }
+                        " "
+                    }
+                }
+            };
+
+            let label = match &patch.pre {
+                Some(pre) => format!("v{major_version}.{minor_version}.{}-{pre}", patch.version),
+                None => format!("v{major_version}.{minor_version}.{}", patch.version),
+            };
+
+            rsx! {
+                div {
+                    h4 {
+                        "{label}"
+                        match patch.published {
+                            true => rsx!{ span { class: "published", " - (Published)" } },
+                            false => rsx!{ span { class: "unpublished", " - (Unpublished)" } },
+                        }
+                        span { class: "churn",
+                            " +{churn.insertions} -{churn.deletions} in {churn.files_changed} files"
                         }
+                    }
+                    if !biggest_changes.is_empty() {
                         div { class: "changed-packages",
-                            div { "Changed packages: " }
+                            div { "Biggest changes: " }
                             div { class: "inline-changed-package-list",
-                                for package in changed_packages {
-                                    a {
-                                        href: "https://github.com/dioxuslabs/dioxus/tree/main/packages/{package}",
-                                        target: "_blank",
-                                        "{package},"
-                                    }
-                                    " "
+                                for (krate, stats) in biggest_changes {
+                                    span { class: "churn", "{krate} (+{stats.insertions} -{stats.deletions}) " }
                                 }
                             }
                         }
-                        ul {
-                            for commit in patch.commits {
-                                {render_commit(commit)}
+                    }
+                    div { class: "changed-packages",
+                        div { "Changed packages: " }
+                        div { class: "inline-changed-package-list",
+                            {render_packages(changed_packages)}
+                        }
+                    }
+                    if !affected_packages.is_empty() {
+                        div { class: "changed-packages affected-packages",
+                            div { "Affected through dependencies: " }
+                            div { class: "inline-changed-package-list",
+                                {render_packages(affected_packages)}
                             }
                         }
                     }
-                }
-            };
-
-            rsx! {
-                div {
-                    for patch in version.patch_versions.into_iter().rev() {
-                        {render_patch(patch)}
+                    if !fixed_issues.is_empty() {
+                        div { class: "fixed-issues",
+                            "Fixes: "
+                            for issue in fixed_issues {
+                                a { href: issue.url(), target: "_blank", "#{issue.number} " }
+                            }
+                        }
+                    }
+                    ul {
+                        for commit in patch.commits {
+                            {render_commit(commit)}
+                        }
                     }
                 }
             }
         };
 
+        let mut patches = version.patch_versions;
+        patches.reverse();
+
+        if sort_value == SortOrder::Churn {
+            patches.sort_by_key(|patch| {
+                let lines: usize = patch
+                    .commits
+                    .iter()
+                    .map(|commit| commit.churn.lines())
+                    .sum();
+                std::cmp::Reverse(lines)
+            });
+        }
+
         rsx! {
-            for version in versions {
-                {render_minor(version)}
+            div {
+                for patch in patches {
+                    {render_patch(patch)}
+                }
             }
         }
-    });
+    };
 
     rsx! {
-        {data()}
+        div { class: "backport-filter",
+            "Show: "
+            select {
+                onchange: move |evt| {
+                    filter.set(match evt.value().as_str() {
+                        "backported" => BackportFilter::Backported,
+                        "not-backported" => BackportFilter::NotBackported,
+                        _ => BackportFilter::All,
+                    })
+                },
+                option { value: "all", "All PRs" }
+                option { value: "backported", "Backported to stable" }
+                option { value: "not-backported", "Not backported" }
+            }
+            " Sort by: "
+            select {
+                onchange: move |evt| {
+                    sort_order.set(match evt.value().as_str() {
+                        "churn" => SortOrder::Churn,
+                        _ => SortOrder::Release,
+                    })
+                },
+                option { value: "release", "Release" }
+                option { value: "churn", "Lines changed" }
+            }
+        }
+        for version in versions {
+            {render_minor(version)}
+        }
+    }
+}
+
+/// Warn about anything `cargo semver-checks` found before it ships
+#[component]
+fn SemverFindings(findings: Vec<SemverFinding>) -> Element {
+    rsx! {
+        ul { class: "semver-findings",
+            for finding in findings {
+                li {
+                    class: match finding.severity {
+                        SemverSeverity::Failure => "semver-failure",
+                        SemverSeverity::Warning => "semver-warning",
+                    },
+                    "{finding.krate}: {finding.lint} - {finding.description}"
+                    if let Some(item) = finding.item.as_ref() {
+                        code { " {item}" }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The issues a PR closes or references, closing ones first
+#[component]
+fn LinkedIssues(issues: Vec<LinkedIssue>) -> Element {
+    let mut issues = issues;
+    issues.sort_by_key(|issue| !issue.closes);
+
+    rsx! {
+        for issue in issues {
+            a {
+                class: "linked-issue",
+                class: if issue.closes { "closes" },
+                href: issue.url(),
+                target: "_blank",
+                if issue.closes {
+                    " fixes #{issue.number}"
+                } else {
+                    " #{issue.number}"
+                }
+            }
+        }
     }
 }